@@ -0,0 +1,322 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::checksum::content_md5;
+use crate::cos::{check_response, read_checked, Client, Error};
+use crate::error::CosError;
+use crate::transport::SendVia;
+
+/// URI of the grantee group that makes a bucket or object public.
+pub const ALL_USERS_URI: &str = "http://acs.amazonaws.com/groups/global/AllUsers";
+
+/// Predefined ACLs, sent in the `x-amz-acl` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CannedAcl {
+    Private,
+    PublicRead,
+}
+
+impl CannedAcl {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CannedAcl::Private => "private",
+            CannedAcl::PublicRead => "public-read",
+        }
+    }
+}
+
+/// Permissions a grant can give.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    FullControl,
+    Read,
+    Write,
+    ReadAcp,
+    WriteAcp,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::FullControl => "FULL_CONTROL",
+            Permission::Read => "READ",
+            Permission::Write => "WRITE",
+            Permission::ReadAcp => "READ_ACP",
+            Permission::WriteAcp => "WRITE_ACP",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = CosError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "FULL_CONTROL" => Ok(Permission::FullControl),
+            "READ" => Ok(Permission::Read),
+            "WRITE" => Ok(Permission::Write),
+            "READ_ACP" => Ok(Permission::ReadAcp),
+            "WRITE_ACP" => Ok(Permission::WriteAcp),
+            _ => Err(CosError::Validation(format!("unknown permission '{}'", s))),
+        }
+    }
+}
+
+/// The `AccessControlPolicy` of a bucket.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct BucketAcl {
+    #[serde(rename = "Owner")]
+    pub owner: AclOwner,
+    #[serde(rename = "AccessControlList", default)]
+    pub access_control_list: AccessControlList,
+}
+
+impl BucketAcl {
+    /// Whether any grant gives access to all users.
+    pub fn is_public(&self) -> bool {
+        self.access_control_list
+            .grants
+            .iter()
+            .any(|g| g.grantee.uri.as_deref() == Some(ALL_USERS_URI))
+    }
+}
+
+/// The `AccessControlPolicy` of an object; same shape as a bucket's.
+pub type ObjectAcl = BucketAcl;
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct AclOwner {
+    #[serde(rename = "$unflatten=ID")]
+    pub id: String,
+    #[serde(rename = "$unflatten=DisplayName", default)]
+    pub display_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct AccessControlList {
+    #[serde(rename = "Grant", default)]
+    pub grants: Vec<Grant>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Grant {
+    #[serde(rename = "Grantee")]
+    pub grantee: Grantee,
+    /// e.g. `FULL_CONTROL`, `READ`, `WRITE`
+    #[serde(rename = "$unflatten=Permission")]
+    pub permission: String,
+}
+
+impl Grant {
+    /// `permission` checked against the known set.
+    pub fn parse_permission(&self) -> Result<Permission, CosError> {
+        self.permission.parse()
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Grantee {
+    /// `CanonicalUser` or `Group`, from the `xsi:type` attribute
+    #[serde(rename = "type", alias = "xsi:type", default)]
+    pub grantee_type: String,
+    #[serde(rename = "$unflatten=ID", default)]
+    pub id: Option<String>,
+    #[serde(rename = "$unflatten=DisplayName", default)]
+    pub display_name: Option<String>,
+    #[serde(rename = "$unflatten=URI", default)]
+    pub uri: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+#[serde(rename = "PublicAccessBlockConfiguration")]
+pub struct PublicAccessBlockConfiguration {
+    /// Reject requests that would set a public ACL
+    #[serde(rename = "$unflatten=BlockPublicAcls", default)]
+    pub block_public_acls: bool,
+    /// Ignore public ACLs already set on the bucket and its objects
+    #[serde(rename = "$unflatten=IgnorePublicAcls", default)]
+    pub ignore_public_acls: bool,
+}
+
+impl Client {
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_bucket_acl(&self, bucket: &str) -> Result<BucketAcl, Error> {
+        let c = &self.client;
+        let url = format!("{}?acl", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        Ok(from_str(&text)?)
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn put_bucket_acl(&self, bucket: &str, acl: CannedAcl) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?acl", self.bucket_url(bucket));
+
+        let response = c
+            .put(url)
+            .header("x-amz-acl", acl.as_str())
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn get_object_acl(&self, bucket: &str, key: &str) -> Result<ObjectAcl, Error> {
+        let c = &self.client;
+        let url = format!("{}{}?acl", self.bucket_url(bucket), key);
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        Ok(from_str(&text)?)
+    }
+
+    /// Replace the ACL of an existing object, e.g. to make one object public
+    /// in a private bucket. Set `PutObjectOptions::acl` to do so on upload.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn put_object_acl(&self, bucket: &str, key: &str, acl: CannedAcl) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}{}?acl", self.bucket_url(bucket), key);
+
+        let response = c
+            .put(url)
+            .header("x-amz-acl", acl.as_str())
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_public_access_block(
+        &self,
+        bucket: &str,
+    ) -> Result<PublicAccessBlockConfiguration, Error> {
+        let c = &self.client;
+        let url = format!("{}?publicAccessBlock", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        Ok(from_str(&text)?)
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn put_public_access_block(
+        &self,
+        bucket: &str,
+        config: &PublicAccessBlockConfiguration,
+    ) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?publicAccessBlock", self.bucket_url(bucket));
+
+        let payload = to_string(config)?;
+
+        let response = c
+            .put(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn delete_public_access_block(&self, bucket: &str) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?publicAccessBlock", self.bucket_url(bucket));
+
+        let response = c.delete(url).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bucket_acl() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><AccessControlPolicy xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Owner><ID>d6f04d83</ID><DisplayName>d6f04d83</DisplayName></Owner><AccessControlList><Grant><Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="CanonicalUser"><ID>d6f04d83</ID><DisplayName>d6f04d83</DisplayName></Grantee><Permission>FULL_CONTROL</Permission></Grant><Grant><Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="Group"><URI>http://acs.amazonaws.com/groups/global/AllUsers</URI></Grantee><Permission>READ</Permission></Grant></AccessControlList></AccessControlPolicy>"#;
+
+        let acl: BucketAcl = from_str(input).unwrap();
+        assert_eq!(acl.owner.id, "d6f04d83");
+
+        let grants = &acl.access_control_list.grants;
+        assert_eq!(grants.len(), 2);
+        assert_eq!(grants[0].grantee.grantee_type, "CanonicalUser");
+        assert_eq!(grants[0].permission, "FULL_CONTROL");
+        assert_eq!(grants[1].grantee.grantee_type, "Group");
+        assert_eq!(grants[1].grantee.uri.as_deref(), Some(ALL_USERS_URI));
+        assert!(acl.is_public());
+        assert_eq!(grants[1].parse_permission().unwrap(), Permission::Read);
+    }
+
+    #[test]
+    fn test_object_acl() {
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(200, "")
+            .respond(200, "<AccessControlPolicy><Owner><ID>o</ID></Owner><AccessControlList><Grant><Grantee><ID>o</ID></Grantee><Permission>FULL_CONTROL</Permission></Grant></AccessControlList></AccessControlPolicy>");
+
+        client
+            .put_object_acl("bucket", "k", CannedAcl::PublicRead)
+            .unwrap();
+        let acl = client.get_object_acl("bucket", "k").unwrap();
+        assert_eq!(acl.owner.id, "o");
+        assert!(!acl.is_public());
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].url, "https://bucket.s3.example.com/k?acl");
+        assert_eq!(requests[0].headers["x-amz-acl"], "public-read");
+        assert_eq!(requests[1].method, reqwest::Method::GET);
+    }
+
+    #[test]
+    fn test_unknown_permission() {
+        assert!("SUPER_USER".parse::<Permission>().is_err());
+        assert_eq!(
+            "WRITE_ACP".parse::<Permission>().unwrap().as_str(),
+            "WRITE_ACP"
+        );
+    }
+
+    #[test]
+    fn test_public_access_block_xml() {
+        let config = PublicAccessBlockConfiguration {
+            block_public_acls: true,
+            ignore_public_acls: false,
+        };
+
+        let exp = "<PublicAccessBlockConfiguration><BlockPublicAcls>true</BlockPublicAcls><IgnorePublicAcls>false</IgnorePublicAcls></PublicAccessBlockConfiguration>";
+        assert_eq!(to_string(&config).unwrap(), exp);
+        assert_eq!(
+            from_str::<PublicAccessBlockConfiguration>(exp).unwrap(),
+            config
+        );
+    }
+}
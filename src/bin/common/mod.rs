@@ -0,0 +1,94 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers shared by the binaries.
+
+use std::collections::VecDeque;
+use std::process::ExitCode;
+
+use ibmcloud_cos::cos::Error;
+use ibmcloud_cos::endpoint::Endpoint;
+use ibmcloud_cos::error::exit_code;
+
+/// `--help` text for the codes `exit_code` returns.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  any other failure
+  2  invalid arguments
+  3  the bucket or object does not exist
+  4  the credentials are missing, invalid, or lack permission
+  5  COS asked to slow down (429, 503)";
+
+/// Print the error of `run`, if any, and turn the result into the process
+/// exit code.
+pub fn report(result: Result<(), Error>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// The endpoint and bucket of a command line like `[ENDPOINT] [BUCKET] KEY`,
+/// as the binaries take them.
+///
+/// Each is taken from the front of `args` unless it was already given, e.g.
+/// with an option or an environment variable.
+pub fn take_target(
+    endpoint: Option<Endpoint>,
+    bucket: Option<String>,
+    args: &mut VecDeque<String>,
+) -> Result<(Endpoint, String), Error> {
+    let endpoint = match endpoint {
+        Some(e) => e,
+        None => args.pop_front().ok_or("missing ENDPOINT")?.parse()?,
+    };
+    let bucket = match bucket {
+        Some(b) => b,
+        None => args.pop_front().ok_or("missing BUCKET")?,
+    };
+
+    Ok((endpoint, bucket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_target() {
+        let mut args = VecDeque::from(["us-south".to_string(), "b".to_string(), "k".to_string()]);
+        let (endpoint, bucket) = take_target(None, None, &mut args).unwrap();
+        assert_eq!(
+            (endpoint, bucket.as_str()),
+            (Endpoint::public("us-south"), "b")
+        );
+        assert_eq!(args, ["k"]);
+
+        let mut args = VecDeque::from(["k".to_string()]);
+        let (_, bucket) = take_target(
+            Some(Endpoint::public("eu-de")),
+            Some("b".to_string()),
+            &mut args,
+        )
+        .unwrap();
+        assert_eq!(bucket, "b");
+        assert_eq!(args, ["k"]);
+
+        assert!(take_target(None, None, &mut VecDeque::from(["us-south".to_string()])).is_err());
+    }
+}
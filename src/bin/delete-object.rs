@@ -12,34 +12,163 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
-use ibmcloud_iam::token::TokenManager;
-use tracing_subscriber;
 
 use ibmcloud_cos::cos;
+use ibmcloud_cos::credentials::IamTokenProvider;
+use ibmcloud_cos::endpoint::Endpoint;
+use ibmcloud_cos::versioning::VersionEntry;
+
+mod common;
+
+use common::{report, take_target, EXIT_CODES_HELP};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
-    endpoint: String,
-    bucket: String,
-    keys: Vec<String>,
+    /// Region or host, e.g. `us-south` or `private:eu-de`, instead of the first argument
+    #[arg(short, long, env = "COS_ENDPOINT")]
+    endpoint: Option<Endpoint>,
+    /// Bucket, instead of the argument after the endpoint
+    #[arg(short, long, env = "COS_BUCKET")]
+    bucket: Option<String>,
+    /// `[ENDPOINT] [BUCKET] KEY...`
+    #[arg(value_name = "ARGS", required = true)]
+    args: Vec<String>,
+    /// Print the keys that would be deleted without deleting them
+    #[arg(long)]
+    dry_run: bool,
+    /// Permanently delete this version of a single key
+    #[arg(long, conflicts_with = "all_versions")]
+    version_id: Option<String>,
+    /// Permanently delete every version and delete marker of the keys,
+    /// instead of adding a delete marker in a versioned bucket
+    #[arg(long)]
+    all_versions: bool,
+    /// Request timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Retry requests that fail with a server error or can't connect up to
+    /// this many times
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
 
     let args = Args::parse();
 
-    let tm = Arc::new(TokenManager::default());
-    let c = cos::Client::new(tm, &args.endpoint);
+    report(run(args))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut keys = VecDeque::from(args.args);
+    let (endpoint, bucket) = take_target(args.endpoint, args.bucket, &mut keys)?;
+    let keys = Vec::from(keys);
+    if keys.is_empty() {
+        return Err("missing KEY".into());
+    }
+
+    let tm = Arc::new(IamTokenProvider::from_env()?);
+    let mut builder = cos::Client::builder(tm, &endpoint.host());
+    if let Some(secs) = args.timeout {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+    builder = builder.retries(args.retries);
+    let c = builder.build()?;
+
+    if let Some(version_id) = &args.version_id {
+        let [key] = &keys[..] else {
+            return Err("--version-id takes exactly one key".into());
+        };
+        return delete_version(&c, &bucket, key, version_id, false, args.dry_run);
+    }
+
+    if args.all_versions {
+        for key in &keys {
+            let versions = key_versions(&c, &bucket, key)?;
+
+            if versions.is_empty() {
+                eprintln!("No versions of {}/{}", bucket, key);
+            }
+
+            for v in versions {
+                let marker = matches!(v, VersionEntry::DeleteMarker(_));
+                delete_version(&c, &bucket, key, v.version_id(), marker, args.dry_run)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    for key in &keys {
+        if args.dry_run {
+            eprintln!("Would delete {}/{}", bucket, key);
+            continue;
+        }
+
+        eprintln!("Deleting {}/{}", bucket, key);
+        c.delete_object(&bucket, key)?;
+    }
+
+    Ok(())
+}
+
+/// Every version and delete marker of `key`, failing if a page can't be listed.
+fn key_versions(
+    c: &cos::Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Vec<VersionEntry>, Box<dyn std::error::Error>> {
+    let mut versions = Vec::new();
+    let (mut key_marker, mut version_id_marker) = (None, None);
 
-    for key in args.keys {
-        eprintln!("Deleting {}/{}", args.bucket, key);
-        c.delete_object(&args.bucket, &key)?;
+    loop {
+        let page = c.list_object_versions_page(
+            bucket,
+            Some(key.to_string()),
+            key_marker,
+            version_id_marker,
+        )?;
+
+        // the listing is by prefix, so skip longer keys that share it
+        versions.extend(page.entries.into_iter().filter(|v| v.key() == key));
+
+        if !page.is_truncated || page.next_key_marker.is_none() {
+            return Ok(versions);
+        }
+        key_marker = page.next_key_marker;
+        version_id_marker = page.next_version_id_marker;
     }
+}
+
+fn delete_version(
+    c: &cos::Client,
+    bucket: &str,
+    key: &str,
+    version_id: &str,
+    delete_marker: bool,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let what = if delete_marker {
+        "delete marker"
+    } else {
+        "version"
+    };
+
+    if dry_run {
+        eprintln!("Would delete {}/{} {} {}", bucket, key, what, version_id);
+        return Ok(());
+    }
+
+    c.delete_object_version(bucket, key, Some(version_id))?;
+    eprintln!("Deleted {}/{} {} {}", bucket, key, what, version_id);
 
     Ok(())
 }
@@ -12,33 +12,104 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
-use ibmcloud_iam::token::TokenManager;
-use tracing_subscriber;
 
 use ibmcloud_cos::cos;
+use ibmcloud_cos::credentials::IamTokenProvider;
+use ibmcloud_cos::endpoint::Endpoint;
+use ibmcloud_cos::transfer::DEFAULT_PART_SIZE;
+
+mod common;
+
+use common::{report, take_target, EXIT_CODES_HELP};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
-    endpoint: String,
-    bucket: String,
-    key: String,
+    /// Region or host, e.g. `us-south` or `private:eu-de`, instead of the first argument
+    #[arg(short, long, env = "COS_ENDPOINT")]
+    endpoint: Option<Endpoint>,
+    /// Bucket, instead of the argument after the endpoint
+    #[arg(short, long, env = "COS_BUCKET")]
+    bucket: Option<String>,
+    /// `[ENDPOINT] [BUCKET] KEY`
+    #[arg(value_name = "ARGS", required = true, num_args = 1..=3)]
+    args: Vec<String>,
+    /// Read a public object without credentials
+    #[arg(long)]
+    anonymous: bool,
+    /// Request timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Retry requests that fail with a server error or can't connect up to
+    /// this many times
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Resume a download whose body breaks off up to this many times, from
+    /// where it stopped
+    #[arg(long, default_value_t = 0, conflicts_with = "output")]
+    max_resumes: u32,
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Download this many byte ranges of the object at once
+    #[arg(long, default_value_t = 1, requires = "output")]
+    concurrency: usize,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
 
     let args = Args::parse();
 
-    eprintln!("Downloading {}/{}", args.bucket, args.key);
+    report(run(args))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rest = VecDeque::from(args.args);
+    let (endpoint, bucket) = take_target(args.endpoint, args.bucket, &mut rest)?;
+    let [key]: [String; 1] = Vec::from(rest)
+        .try_into()
+        .map_err(|_| "expected [ENDPOINT] [BUCKET] KEY")?;
+
+    eprintln!("Downloading {}/{}", bucket, key);
+
+    let mut builder = if args.anonymous {
+        cos::ClientBuilder::anonymous(&endpoint.host())
+    } else {
+        cos::Client::builder(Arc::new(IamTokenProvider::from_env()?), &endpoint.host())
+    };
+    if let Some(secs) = args.timeout {
+        builder = builder
+            .timeout(Duration::from_secs(secs))
+            .download_timeout(Duration::from_secs(secs));
+    }
+    builder = builder.retries(args.retries);
+    let c = builder.build()?;
 
-    let tm = Arc::new(TokenManager::default());
-    let c = cos::Client::new(tm, &args.endpoint);
+    if let Some(path) = &args.output {
+        c.download_ranged(
+            &bucket,
+            &key,
+            path,
+            args.concurrency,
+            DEFAULT_PART_SIZE as u64,
+        )?;
+        return Ok(());
+    }
 
-    let mut r = c.get_object(&args.bucket, &args.key)?;
+    let mut r: Box<dyn Read> = if args.max_resumes > 0 {
+        Box::new(c.get_object_resumable(&bucket, &key, args.max_resumes)?)
+    } else {
+        c.get_object(&bucket, &key)?
+    };
     let mut stdout = std::io::stdout().lock();
 
     std::io::copy(&mut r, &mut stdout)?;
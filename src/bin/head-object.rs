@@ -0,0 +1,134 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+
+use ibmcloud_cos::cos;
+use ibmcloud_cos::credentials::IamTokenProvider;
+use ibmcloud_cos::endpoint::Endpoint;
+
+mod common;
+
+use common::{report, take_target, EXIT_CODES_HELP};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
+struct Args {
+    /// Region or host, e.g. `us-south` or `private:eu-de`, instead of the first argument
+    #[arg(short, long, env = "COS_ENDPOINT")]
+    endpoint: Option<Endpoint>,
+    /// Bucket, instead of the argument after the endpoint
+    #[arg(short, long, env = "COS_BUCKET")]
+    bucket: Option<String>,
+    /// `[ENDPOINT] [BUCKET] KEY`
+    #[arg(value_name = "ARGS", required = true, num_args = 1..=3)]
+    args: Vec<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Read a public object without credentials
+    #[arg(long)]
+    anonymous: bool,
+    /// Request timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Retry requests that fail with a server error or can't connect up to
+    /// this many times
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// `name: value` lines
+    Text,
+    /// A single JSON object
+    Json,
+}
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt::init();
+
+    let args = Args::parse();
+
+    report(run(args))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rest = VecDeque::from(args.args);
+    let (endpoint, bucket) = take_target(args.endpoint, args.bucket, &mut rest)?;
+    let [key]: [String; 1] = Vec::from(rest)
+        .try_into()
+        .map_err(|_| "expected [ENDPOINT] [BUCKET] KEY")?;
+
+    let mut builder = if args.anonymous {
+        cos::ClientBuilder::anonymous(&endpoint.host())
+    } else {
+        cos::Client::builder(Arc::new(IamTokenProvider::from_env()?), &endpoint.host())
+    };
+    if let Some(secs) = args.timeout {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+    builder = builder.retries(args.retries);
+    let c = builder.build()?;
+
+    let meta = c.head_object(&bucket, &key)?;
+
+    match args.format {
+        Format::Text => {
+            let none = String::new();
+            println!("size: {}", meta.content_length);
+            println!(
+                "content-type: {}",
+                meta.content_type.as_ref().unwrap_or(&none)
+            );
+            println!("etag: {}", meta.etag.as_ref().unwrap_or(&none));
+            println!(
+                "last-modified: {}",
+                meta.last_modified.as_ref().unwrap_or(&none)
+            );
+            if let Some(class) = &meta.storage_class {
+                println!("storage-class: {}", class);
+            }
+            if let Some(version) = &meta.version_id {
+                println!("version-id: {}", version);
+            }
+
+            let mut metadata: Vec<_> = meta.metadata.iter().collect();
+            metadata.sort();
+            for (k, v) in metadata {
+                println!("x-amz-meta-{}: {}", k, v);
+            }
+        }
+        Format::Json => println!(
+            "{}",
+            serde_json::json!({
+                "size": meta.content_length,
+                "content_type": meta.content_type,
+                "etag": meta.etag,
+                "last_modified": meta.last_modified,
+                "storage_class": meta.storage_class,
+                "version_id": meta.version_id,
+                "metadata": meta.metadata,
+            })
+        ),
+    }
+
+    Ok(())
+}
@@ -12,34 +12,233 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
-use ibmcloud_iam::token::TokenManager;
-use tracing_subscriber;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
 
 use ibmcloud_cos::cos;
+use ibmcloud_cos::credentials::IamTokenProvider;
+use ibmcloud_cos::endpoint::Endpoint;
+
+mod common;
+
+use common::{report, take_target, EXIT_CODES_HELP};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
-    endpoint: String,
-    bucket: String,
-    prefix: Option<String>,
+    /// Region or host, e.g. `us-south` or `private:eu-de`, instead of the first argument
+    #[arg(short, long, env = "COS_ENDPOINT")]
+    endpoint: Option<Endpoint>,
+    /// Bucket, instead of the argument after the endpoint
+    #[arg(short, long, env = "COS_BUCKET")]
+    bucket: Option<String>,
+    /// `[ENDPOINT] [BUCKET] [PREFIX]`
+    #[arg(value_name = "ARGS", num_args = 0..=3)]
+    args: Vec<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Group keys by common prefix up to this delimiter, e.g. `/`
+    #[arg(long)]
+    delimiter: Option<String>,
+    /// Also show the ETag and storage class
+    #[arg(short, long)]
+    long: bool,
+    /// Print sizes like `4.2M`
+    #[arg(short = 'H', long)]
+    human_readable: bool,
+    /// List a public bucket without credentials
+    #[arg(long)]
+    anonymous: bool,
+    /// Print only the keys, one per line, e.g. for `xargs`
+    #[arg(long, conflicts_with_all = ["format", "long", "human_readable"])]
+    keys_only: bool,
+    /// Request timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Retry requests that fail with a server error or can't connect up to
+    /// this many times
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// `{last_modified} {size} {key}` lines
+    Text,
+    /// One JSON object per line
+    Json,
+    Csv,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    key: &'a str,
+    last_modified: &'a str,
+    etag: &'a str,
+    size: u64,
+    storage_class: &'a str,
+}
+
+impl<'a> From<&'a cos::Contents> for Row<'a> {
+    fn from(c: &'a cos::Contents) -> Self {
+        Row {
+            key: &c.key,
+            last_modified: &c.last_modified,
+            etag: &c.etag,
+            size: c.size,
+            storage_class: &c.storage_class,
+        }
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut value = size as f64;
+    let mut unit = "";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+
+    if value < 10.0 {
+        format!("{:.1}{}", value, unit)
+    } else {
+        format!("{:.0}{}", value, unit)
+    }
+}
+
+fn print_object(args: &Args, obj: &cos::Contents) -> Result<(), serde_json::Error> {
+    if args.keys_only {
+        println!("{}", obj.key);
+        return Ok(());
+    }
+
+    match args.format {
+        Format::Text => {
+            let size = if args.human_readable {
+                human_size(obj.size)
+            } else {
+                obj.size.to_string()
+            };
+
+            if args.long {
+                println!(
+                    "{} {:>10} {} {:<8} {}",
+                    obj.last_modified, size, obj.etag, obj.storage_class, obj.key
+                );
+            } else if args.human_readable {
+                println!("{} {:>10} {}", obj.last_modified, size, obj.key);
+            } else {
+                println!("{}", obj);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(&Row::from(obj))?),
+        Format::Csv => println!(
+            "{},{},{},{},{}",
+            csv_field(&obj.key),
+            obj.last_modified,
+            csv_field(&obj.etag),
+            obj.size,
+            obj.storage_class
+        ),
+    }
+
+    Ok(())
+}
+
+fn print_prefix(args: &Args, prefix: &str) {
+    if args.keys_only {
+        return;
+    }
+
+    match args.format {
+        Format::Text => println!("{:>24} {:>10} {}", "", "PRE", prefix),
+        Format::Json => println!("{}", serde_json::json!({ "prefix": prefix })),
+        Format::Csv => println!("{},,,,", csv_field(prefix)),
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
 
     let args = Args::parse();
 
-    eprintln!("Listing {}", args.bucket);
+    report(run(args))
+}
+
+fn run(mut args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rest = VecDeque::from(std::mem::take(&mut args.args));
+    let (endpoint, bucket) = take_target(args.endpoint.take(), args.bucket.take(), &mut rest)?;
+    let prefix = rest.pop_front();
+    if !rest.is_empty() {
+        return Err("expected [ENDPOINT] [BUCKET] [PREFIX]".into());
+    }
+
+    eprintln!("Listing {}", bucket);
+
+    let mut builder = if args.anonymous {
+        cos::ClientBuilder::anonymous(&endpoint.host())
+    } else {
+        cos::Client::builder(Arc::new(IamTokenProvider::from_env()?), &endpoint.host())
+    };
+    if let Some(secs) = args.timeout {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+    builder = builder.retries(args.retries);
+    let c = builder.build()?;
+
+    if let (Format::Csv, false) = (args.format, args.keys_only) {
+        println!("key,last_modified,etag,size,storage_class");
+    }
+
+    if let Some(delimiter) = &args.delimiter {
+        let listing = c.list_objects_delimited(&bucket, prefix.clone(), delimiter)?;
+
+        for prefix in &listing.common_prefixes {
+            print_prefix(&args, prefix);
+        }
+
+        for obj in &listing.objects {
+            print_object(&args, obj)?;
+        }
+    } else {
+        // page by page rather than with list_objects, so a failed page is
+        // returned instead of ending the listing early
+        let mut token = None;
+        loop {
+            let page = c.list_objects_page(&bucket, prefix.clone(), token, None)?;
 
-    let tm = Arc::new(TokenManager::default());
-    let c = cos::Client::new(tm, &args.endpoint);
+            for obj in &page.contents {
+                print_object(&args, obj)?;
+            }
 
-    for obj in c.list_objects(&args.bucket, args.prefix, None) {
-        println!("{} {:>10} {}", obj.last_modified, obj.size, obj.key);
+            match page.next_token {
+                Some(t) if page.is_truncated => token = Some(t),
+                _ => break,
+            }
+        }
     }
 
     Ok(())
@@ -12,65 +12,85 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
-use ibmcloud_iam::token::TokenManager;
-use tracing_subscriber;
 
 use ibmcloud_cos::cos;
-use ibmcloud_cos::multipartupload::{CompleteMultipartUpload, Part};
+use ibmcloud_cos::credentials::IamTokenProvider;
+use ibmcloud_cos::endpoint::Endpoint;
+use ibmcloud_cos::object::PutObjectOptions;
 
-const MB: usize = 1 * 1024 * 1024;
+const MB: usize = 1024 * 1024;
+
+mod common;
+
+use common::{report, take_target, EXIT_CODES_HELP};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
-    endpoint: String,
-    bucket: String,
-    key: String,
-    filename: PathBuf,
+    /// Region or host, e.g. `us-south` or `private:eu-de`, instead of the first argument
+    #[arg(short, long, env = "COS_ENDPOINT")]
+    endpoint: Option<Endpoint>,
+    /// Bucket, instead of the argument after the endpoint
+    #[arg(short, long, env = "COS_BUCKET")]
+    bucket: Option<String>,
+    /// `[ENDPOINT] [BUCKET] KEY FILENAME`
+    #[arg(value_name = "ARGS", required = true, num_args = 2..=4)]
+    args: Vec<String>,
+    /// Request timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Retry requests that fail with a server error or can't connect up to
+    /// this many times
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Upload this many 5 MiB parts at once
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
 
     let args = Args::parse();
 
-    let tm = Arc::new(TokenManager::default());
-
-    let c = cos::Client::new(tm, &args.endpoint);
-
-    let mut file = File::open(args.filename)?;
-    let mut parts: Vec<Part> = Vec::new();
-
-    let upload_id = c.create_multipart_upload(&args.bucket, &args.key)?;
-
-    loop {
-        let mut chunk = vec![0u8; 5 * MB];
-
-        let n = file.read(&mut chunk[..])?;
-
-        if n == 0 {
-            break;
-        }
-
-        chunk.truncate(n);
-
-        let seq_no = parts.len() + 1;
-
-        let part = c.upload_part(&args.bucket, &args.key, &upload_id, seq_no, chunk)?;
-        parts.push(part);
-    }
-
-    let cmu = CompleteMultipartUpload { parts };
+    report(run(args))
+}
 
-    if let Err(_) = c.complete_multipart_upload(&args.bucket, &args.key, &upload_id, cmu) {
-        let _ = c.abort_multipart_upload(&args.bucket, &args.key, &upload_id)?;
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rest = VecDeque::from(args.args);
+    let (endpoint, bucket) = take_target(args.endpoint, args.bucket, &mut rest)?;
+    let [key, filename]: [String; 2] = Vec::from(rest)
+        .try_into()
+        .map_err(|_| "expected [ENDPOINT] [BUCKET] KEY FILENAME")?;
+
+    let tm = Arc::new(IamTokenProvider::from_env()?);
+    let mut builder = cos::Client::builder(tm, &endpoint.host());
+    if let Some(secs) = args.timeout {
+        builder = builder
+            .timeout(Duration::from_secs(secs))
+            .upload_timeout(Duration::from_secs(secs));
     }
+    builder = builder.retries(args.retries);
+    let c = builder.build()?;
+
+    let file = File::open(filename)?;
+
+    // aborts the upload if any part or the completion fails
+    c.upload_multipart_concurrent(
+        &bucket,
+        &key,
+        file,
+        5 * MB,
+        args.concurrency,
+        &PutObjectOptions::default(),
+    )?;
 
     Ok(())
 }
@@ -0,0 +1,128 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+
+use ibmcloud_cos::cos;
+use ibmcloud_cos::credentials::IamTokenProvider;
+use ibmcloud_cos::endpoint::Endpoint;
+use ibmcloud_cos::multipartupload::MAX_PARTS;
+use ibmcloud_cos::object::{content_type_from_extension, PutObjectOptions, StorageClass};
+use ibmcloud_cos::transfer::{DEFAULT_PART_SIZE, MULTIPART_THRESHOLD};
+
+mod common;
+
+use common::{report, take_target, EXIT_CODES_HELP};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
+struct Args {
+    /// Region or host, e.g. `us-south` or `private:eu-de`, instead of the first argument
+    #[arg(short, long, env = "COS_ENDPOINT")]
+    endpoint: Option<Endpoint>,
+    /// Bucket, instead of the argument after the endpoint
+    #[arg(short, long, env = "COS_BUCKET")]
+    bucket: Option<String>,
+    /// `[ENDPOINT] [BUCKET] KEY [FILE]`; reads stdin when FILE is omitted or `-`
+    #[arg(value_name = "ARGS", required = true, num_args = 1..=4)]
+    args: Vec<String>,
+    /// Defaults to a type guessed from the key's extension
+    #[arg(long)]
+    content_type: Option<String>,
+    #[arg(long)]
+    storage_class: Option<StorageClass>,
+    /// Request timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Retry requests that fail with a server error or can't connect up to
+    /// this many times
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+}
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt::init();
+
+    let args = Args::parse();
+
+    report(run(args))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rest = VecDeque::from(args.args);
+    let (endpoint, bucket) = take_target(args.endpoint, args.bucket, &mut rest)?;
+    let key = rest.pop_front().ok_or("missing KEY")?;
+    let file = rest.pop_front().map(PathBuf::from);
+    if !rest.is_empty() {
+        return Err("expected [ENDPOINT] [BUCKET] KEY [FILE]".into());
+    }
+
+    let (mut r, size): (Box<dyn Read>, Option<u64>) = match &file {
+        Some(path) if path.as_os_str() != "-" => {
+            let f = File::open(path)?;
+            let size = f.metadata()?.len();
+            (Box::new(f), Some(size))
+        }
+        _ => (Box::new(std::io::stdin().lock()), None),
+    };
+
+    let options = PutObjectOptions {
+        content_type: args
+            .content_type
+            .clone()
+            .or_else(|| content_type_from_extension(&key).map(String::from)),
+        storage_class: args.storage_class,
+        ..Default::default()
+    };
+
+    let tm = Arc::new(IamTokenProvider::from_env()?);
+    let mut builder = cos::Client::builder(tm, &endpoint.host());
+    if let Some(secs) = args.timeout {
+        builder = builder
+            .timeout(Duration::from_secs(secs))
+            .upload_timeout(Duration::from_secs(secs));
+    }
+    builder = builder.retries(args.retries);
+    let c = builder.build()?;
+
+    eprintln!("Uploading {}/{}", bucket, key);
+
+    // buffer up to the threshold to tell small inputs from large ones
+    let mut head = Vec::new();
+    r.by_ref()
+        .take(MULTIPART_THRESHOLD)
+        .read_to_end(&mut head)?;
+
+    if (head.len() as u64) < MULTIPART_THRESHOLD {
+        c.put_object_with_options(&bucket, &key, head, &options)?;
+    } else {
+        let part_size = size
+            .map(|s| (s as usize).div_ceil(MAX_PARTS))
+            .unwrap_or(0)
+            .max(DEFAULT_PART_SIZE);
+
+        let r = Cursor::new(head).chain(r);
+        c.upload_multipart(&bucket, &key, r, part_size, &options)?;
+    }
+
+    Ok(())
+}
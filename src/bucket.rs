@@ -0,0 +1,257 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::acl::CannedAcl;
+use crate::cos::{check_response, read_checked, Client, Error};
+use crate::error::{api_error_code, CosError};
+use crate::transport::SendVia;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct CreateBucketConfiguration {
+    #[serde(rename = "$unflatten=LocationConstraint")]
+    pub location_constraint: String,
+}
+
+/// The location reported for buckets in the legacy `us-standard` region, which
+/// COS returns as an empty `LocationConstraint`
+pub const DEFAULT_LOCATION: &str = "us-standard";
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct LocationConstraint {
+    #[serde(rename = "$value", default)]
+    location: Option<String>,
+}
+
+/// Object count and total size under a prefix, see `bucket_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BucketStats {
+    pub count: u64,
+    /// Sum of the object sizes, in bytes
+    pub total_size: u64,
+}
+
+fn parse_location(text: &str) -> Result<String, Error> {
+    let loc: LocationConstraint = from_str(text)?;
+
+    match loc.location {
+        Some(l) if !l.trim().is_empty() => Ok(l),
+        _ => Ok(DEFAULT_LOCATION.to_string()),
+    }
+}
+
+impl Client {
+    /// Count the objects under `prefix` (the whole bucket when `None`) and sum
+    /// their sizes, one listing page at a time.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn bucket_stats(&self, bucket: &str, prefix: Option<String>) -> Result<BucketStats, Error> {
+        let mut stats = BucketStats::default();
+        let mut continuation_token = None;
+
+        loop {
+            let page = self.list_objects_page(bucket, prefix.clone(), continuation_token, None)?;
+
+            stats.count += page.contents.len() as u64;
+            stats.total_size += page.contents.iter().map(|c| c.size).sum::<u64>();
+
+            match page.next_token {
+                Some(tok) if page.is_truncated => continuation_token = Some(tok),
+                _ => return Ok(stats),
+            }
+        }
+    }
+
+    /// Create a bucket in the given service instance; `None` uses the one set
+    /// with `with_instance_id`.
+    ///
+    /// `location_constraint` selects the location and storage class provisioning
+    /// code, e.g. `us-south-standard`; when `None` the endpoint's default is used.
+    pub fn create_bucket<'a>(
+        &'a self,
+        instance_id: impl Into<Option<&'a str>>,
+        bucket: &str,
+        location_constraint: Option<&str>,
+    ) -> Result<(), Error> {
+        self.create_bucket_with_acl(instance_id, bucket, location_constraint, None)
+    }
+
+    /// Like `create_bucket`, applying a canned ACL to the new bucket.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn create_bucket_with_acl<'a>(
+        &'a self,
+        instance_id: impl Into<Option<&'a str>>,
+        bucket: &str,
+        location_constraint: Option<&str>,
+        acl: Option<CannedAcl>,
+    ) -> Result<(), Error> {
+        let instance_id = self.resolve_instance_id(instance_id.into())?;
+        let c = &self.client;
+        let url = self.bucket_url(bucket);
+
+        let mut req = c
+            .put(url)
+            .header("ibm-service-instance-id", instance_id.to_string());
+
+        if let Some(loc) = location_constraint {
+            let config = CreateBucketConfiguration {
+                location_constraint: loc.to_string(),
+            };
+            req = req.body(to_string(&config)?);
+        }
+
+        if let Some(acl) = acl {
+            req = req.header("x-amz-acl", acl.as_str());
+        }
+
+        let response = req.send_via(self)?;
+
+        match check_response(response) {
+            Ok(_) => Ok(()),
+            Err(e) => match api_error_code(&e) {
+                Some("BucketAlreadyExists") => {
+                    Err(CosError::BucketAlreadyExists(bucket.to_string()).into())
+                }
+                Some("BucketAlreadyOwnedByYou") => {
+                    Err(CosError::BucketAlreadyOwnedByYou(bucket.to_string()).into())
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn delete_bucket(&self, bucket: &str) -> Result<(), Error> {
+        let c = &self.client;
+        let url = self.bucket_url(bucket);
+
+        let response = c.delete(url).send_via(self)?;
+
+        match check_response(response) {
+            Ok(_) => Ok(()),
+            Err(e) => match api_error_code(&e) {
+                Some("BucketNotEmpty") => Err(CosError::BucketNotEmpty(bucket.to_string()).into()),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// The location constraint of a bucket, e.g. `us-south-standard`.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_bucket_location(&self, bucket: &str) -> Result<String, Error> {
+        let c = &self.client;
+        let url = format!("{}?location", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        parse_location(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_bucket_configuration() {
+        let config = CreateBucketConfiguration {
+            location_constraint: "us-south-standard".to_string(),
+        };
+
+        let exp = "<CreateBucketConfiguration><LocationConstraint>us-south-standard</LocationConstraint></CreateBucketConfiguration>";
+        assert_eq!(to_string(&config).unwrap(), exp);
+    }
+
+    #[test]
+    fn test_create_bucket_already_exists() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(
+            409,
+            "<Error><Code>BucketAlreadyExists</Code><Message>taken</Message></Error>",
+        );
+
+        let err = client
+            .create_bucket("instance", "taken", Some("us-south-standard"))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::BucketAlreadyExists(b)) if b == "taken"
+        ));
+
+        let req = &transport.requests()[0];
+        assert_eq!(req.method, reqwest::Method::PUT);
+        assert_eq!(req.headers["ibm-service-instance-id"], "instance");
+        assert_eq!(
+            req.body.as_deref(),
+            Some(&b"<CreateBucketConfiguration><LocationConstraint>us-south-standard</LocationConstraint></CreateBucketConfiguration>"[..])
+        );
+    }
+
+    #[test]
+    fn test_create_bucket_uses_stored_instance_id() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let err = client.create_bucket(None, "b", None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::Validation(_))
+        ));
+        assert!(transport.requests().is_empty());
+
+        transport.respond(200, "");
+        let client = client.with_instance_id("instance");
+        client.create_bucket(None, "b", None).unwrap();
+        assert_eq!(
+            transport.requests()[0].headers["ibm-service-instance-id"],
+            "instance"
+        );
+    }
+
+    #[test]
+    fn test_bucket_stats() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let entry = |key: &str, size: u64| crate::transport::mock::contents(key, "abc", size);
+        transport
+            .respond(200, &format!("<ListBucketResult><KeyCount>2</KeyCount><MaxKeys>2</MaxKeys><IsTruncated>true</IsTruncated><NextContinuationToken>t</NextContinuationToken>{}{}</ListBucketResult>", entry("p/a", 10), entry("p/b", 5)))
+            .respond(200, &format!("<ListBucketResult><KeyCount>1</KeyCount><MaxKeys>2</MaxKeys><IsTruncated>false</IsTruncated>{}</ListBucketResult>", entry("p/c", 7)));
+
+        let stats = client
+            .bucket_stats("bucket", Some("p/".to_string()))
+            .unwrap();
+        assert_eq!(
+            stats,
+            BucketStats {
+                count: 3,
+                total_size: 22
+            }
+        );
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_location() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/">eu-de-smart</LocationConstraint>"#;
+        assert_eq!(parse_location(input).unwrap(), "eu-de-smart");
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/"/>"#;
+        assert_eq!(parse_location(input).unwrap(), DEFAULT_LOCATION);
+
+        let input = r#"<LocationConstraint></LocationConstraint>"#;
+        assert_eq!(parse_location(input).unwrap(), DEFAULT_LOCATION);
+    }
+}
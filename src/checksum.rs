@@ -0,0 +1,203 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Read};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+
+use crate::error::CosError;
+
+/// Algorithms for the `x-amz-checksum-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Crc32c,
+}
+
+impl ChecksumAlgorithm {
+    /// The value of `x-amz-checksum-algorithm`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "SHA256",
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+        }
+    }
+
+    /// The header carrying the checksum, e.g. `x-amz-checksum-sha256`.
+    pub fn header_name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "x-amz-checksum-sha256",
+            ChecksumAlgorithm::Crc32c => "x-amz-checksum-crc32c",
+        }
+    }
+
+    /// The base64 checksum of `data`, as sent in the checksum header.
+    pub fn compute(&self, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(*self);
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Crc32c(u32),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Crc32c => Hasher::Crc32c(0),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(h) => STANDARD.encode(h.finalize()),
+            Hasher::Crc32c(crc) => STANDARD.encode(crc.to_be_bytes()),
+        }
+    }
+}
+
+/// The base64 MD5 of `data`, as sent in a `Content-MD5` header.
+pub(crate) fn content_md5(data: &[u8]) -> String {
+    STANDARD.encode(Md5::digest(data))
+}
+
+/// The checksum COS stored or echoed in `headers`, if any. Checksums of
+/// multipart objects (`{checksum}-{parts}`) cover the parts, not the content,
+/// and are skipped.
+pub(crate) fn checksum_from_headers(headers: &HeaderMap) -> Option<(ChecksumAlgorithm, String)> {
+    [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Crc32c]
+        .into_iter()
+        .find_map(|alg| {
+            let value = headers.get(alg.header_name())?.to_str().ok()?;
+            (!value.contains('-')).then(|| (alg, value.to_string()))
+        })
+}
+
+/// Fail unless COS echoed back the `expected` checksum, when it echoes one.
+pub(crate) fn verify_echoed(
+    headers: &HeaderMap,
+    algorithm: ChecksumAlgorithm,
+    expected: &str,
+) -> Result<(), CosError> {
+    match headers
+        .get(algorithm.header_name())
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(actual) if actual != expected => Err(CosError::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Checks a body against its checksum once it has been read to the end, and
+/// fails the final read on a mismatch.
+pub struct ChecksumReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    expected: String,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub(crate) fn new(inner: R, algorithm: ChecksumAlgorithm, expected: String) -> Self {
+        Self {
+            inner,
+            hasher: Some(Hasher::new(algorithm)),
+            expected,
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if n > 0 {
+            if let Some(h) = &mut self.hasher {
+                h.update(&buf[..n]);
+            }
+        } else if !buf.is_empty() {
+            if let Some(h) = self.hasher.take() {
+                let actual = h.finalize();
+                if actual != self.expected {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        CosError::ChecksumMismatch {
+                            expected: self.expected.clone(),
+                            actual,
+                        },
+                    ));
+                }
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        // CRC-32C check value of "123456789" is 0xe3069283
+        assert_eq!(
+            ChecksumAlgorithm::Crc32c.compute(b"123456789"),
+            STANDARD.encode(0xe3069283u32.to_be_bytes())
+        );
+        assert_eq!(ChecksumAlgorithm::Crc32c.compute(b""), "AAAAAA==");
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.compute(b""),
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+    }
+
+    #[test]
+    fn test_content_md5() {
+        assert_eq!(content_md5(b""), "1B2M2Y8AsgTpgAmY7PhCfg==");
+        assert_eq!(content_md5(b"abc"), "kAFQmDzST7DWlj99KOF/cg==");
+    }
+
+    #[test]
+    fn test_checksum_reader() {
+        let good = ChecksumAlgorithm::Crc32c.compute(b"data");
+
+        let mut out = String::new();
+        ChecksumReader::new(&b"data"[..], ChecksumAlgorithm::Crc32c, good)
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "data");
+
+        let err = ChecksumReader::new(&b"data"[..], ChecksumAlgorithm::Crc32c, "bad".to_string())
+            .read_to_string(&mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::checksum::content_md5;
+use crate::cos::{check_response, read_checked, Client, Error};
+use crate::error::CosError;
+use crate::transport::SendVia;
+
+pub const ALLOWED_METHODS: [&str; 5] = ["GET", "PUT", "POST", "DELETE", "HEAD"];
+
+/// (De)serializes a list of strings as repeated elements, e.g.
+/// `<AllowedMethod>GET</AllowedMethod><AllowedMethod>PUT</AllowedMethod>`.
+mod text_list {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize, Serialize)]
+    struct Text {
+        #[serde(rename = "$value", default)]
+        value: String,
+    }
+
+    pub fn serialize<S: Serializer>(list: &[String], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(list.iter().map(|v| Text { value: v.clone() }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+        let list = Vec::<Text>::deserialize(d)?;
+        Ok(list.into_iter().map(|t| t.value).collect())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename = "CORSConfiguration")]
+pub struct CorsConfiguration {
+    #[serde(rename = "CORSRule", default)]
+    pub rules: Vec<CorsRule>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct CorsRule {
+    #[serde(rename = "AllowedOrigin", default, with = "text_list")]
+    pub allowed_origins: Vec<String>,
+    #[serde(rename = "AllowedMethod", default, with = "text_list")]
+    pub allowed_methods: Vec<String>,
+    #[serde(rename = "AllowedHeader", default, with = "text_list")]
+    pub allowed_headers: Vec<String>,
+    #[serde(rename = "ExposeHeader", default, with = "text_list")]
+    pub expose_headers: Vec<String>,
+    #[serde(
+        rename = "$unflatten=MaxAgeSeconds",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_age_seconds: Option<u32>,
+}
+
+pub(crate) fn validate_cors_rules(rules: &[CorsRule]) -> Result<(), CosError> {
+    for rule in rules {
+        if rule.allowed_origins.is_empty() || rule.allowed_methods.is_empty() {
+            return Err(CosError::Validation(
+                "CORS rules need at least one allowed origin and method".to_string(),
+            ));
+        }
+
+        for method in &rule.allowed_methods {
+            if !ALLOWED_METHODS.contains(&method.as_str()) {
+                return Err(CosError::Validation(format!(
+                    "CORS method '{}' is not one of {}",
+                    method,
+                    ALLOWED_METHODS.join(", ")
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Client {
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_bucket_cors(&self, bucket: &str) -> Result<Vec<CorsRule>, Error> {
+        let c = &self.client;
+        let url = format!("{}?cors", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let config: CorsConfiguration = from_str(&text)?;
+
+        Ok(config.rules)
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn put_bucket_cors(&self, bucket: &str, rules: Vec<CorsRule>) -> Result<(), Error> {
+        validate_cors_rules(&rules)?;
+
+        let c = &self.client;
+        let url = format!("{}?cors", self.bucket_url(bucket));
+
+        let payload = to_string(&CorsConfiguration { rules })?;
+
+        let response = c
+            .put(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn delete_bucket_cors(&self, bucket: &str) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?cors", self.bucket_url(bucket));
+
+        let response = c.delete(url).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule() -> CorsRule {
+        CorsRule {
+            allowed_origins: vec!["https://example.com".to_string()],
+            allowed_methods: vec!["GET".to_string(), "HEAD".to_string()],
+            allowed_headers: vec!["*".to_string()],
+            expose_headers: vec!["ETag".to_string()],
+            max_age_seconds: Some(3000),
+        }
+    }
+
+    #[test]
+    fn test_cors_configuration_xml() {
+        let config = CorsConfiguration {
+            rules: vec![rule()],
+        };
+
+        let exp = "<CORSConfiguration><CORSRule><AllowedOrigin>https://example.com</AllowedOrigin><AllowedMethod>GET</AllowedMethod><AllowedMethod>HEAD</AllowedMethod><AllowedHeader>*</AllowedHeader><ExposeHeader>ETag</ExposeHeader><MaxAgeSeconds>3000</MaxAgeSeconds></CORSRule></CORSConfiguration>";
+        let out = to_string(&config).unwrap();
+        assert_eq!(out, exp);
+
+        let parsed: CorsConfiguration = from_str(&out).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn test_validate_cors_rules() {
+        assert!(validate_cors_rules(&[rule()]).is_ok());
+
+        let mut bad = rule();
+        bad.allowed_methods.push("PATCH".to_string());
+        assert!(validate_cors_rules(&[bad]).is_err());
+
+        let mut bad = rule();
+        bad.allowed_origins.clear();
+        assert!(validate_cors_rules(&[bad]).is_err());
+    }
+}
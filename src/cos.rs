@@ -13,41 +13,55 @@
 // limitations under the License.
 
 use std::collections::VecDeque;
+use std::fmt;
 use std::io::Read;
+use std::ops::RangeBounds;
 use std::sync::Arc;
+use std::time::Duration;
 
-use ibmcloud_iam::token::TokenManager;
+use chrono::{DateTime, Utc};
 use quick_xml::de::from_str;
 use reqwest;
+use reqwest::StatusCode;
 use serde;
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{debug_span, error, instrument};
+use urlencoding::encode;
+
+use crate::credentials::CredentialProvider;
+use crate::endpoint::AddressingStyle;
+use crate::error::{ApiError, CosError};
+use crate::object::{
+    deserialize_etag, GetObjectRangeOutput, PutObjectOptions, PutObjectOutput, StorageClass,
+};
+use crate::transport::{Auth, SendVia, Transport};
 
 pub type Error = Box<dyn std::error::Error>;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct ListAllMyBucketsResult {
     #[serde(rename = "Owner")]
-    owner: Owner,
+    pub owner: Owner,
     #[serde(rename = "Buckets")]
-    buckets: Buckets,
+    pub buckets: Buckets,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Buckets {
     #[serde(rename = "Bucket")]
-    list: Vec<Bucket>,
+    pub list: Vec<Bucket>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Owner {
+    /// Canonical ID of the account owning the service instance
     #[serde(rename = "$unflatten=ID")]
-    id: String,
+    pub id: String,
     #[serde(rename = "$unflatten=DisplayName")]
-    display_name: String,
+    pub display_name: String,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Bucket {
     #[serde(rename = "$unflatten=Name")]
     pub name: String,
@@ -55,29 +69,90 @@ pub struct Bucket {
     pub creation_date: String,
 }
 
-fn default_contents() -> Vec<Contents> {
-    Vec::new()
-}
-
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct ListBucketResult {
-    #[serde(rename = "Contents", default = "default_contents")]
-    contents: Vec<Contents>,
+    /// Empty when no keys matched, e.g. for an empty bucket
+    #[serde(rename = "Contents", default)]
+    pub contents: Vec<Contents>,
     #[serde(rename = "$unflatten=KeyCount")]
-    key_count: u64,
+    pub key_count: u64,
     #[serde(rename = "$unflatten=MaxKeys")]
-    max_keys: u64,
+    pub max_keys: u64,
+    #[serde(rename = "$unflatten=IsTruncated", default)]
+    pub is_truncated: bool,
     #[serde(rename = "$unflatten=NextContinuationToken")]
-    next_token: Option<String>,
+    pub next_token: Option<String>,
+    #[serde(rename = "CommonPrefixes", default)]
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommonPrefix {
+    #[serde(rename = "$unflatten=Prefix")]
+    pub prefix: String,
+}
+
+/// A page of a version 1 (`marker` based) listing.
+#[derive(Deserialize, Debug, PartialEq)]
+struct ListBucketResultV1 {
+    #[serde(rename = "Contents", default)]
+    contents: Vec<Contents>,
+    #[serde(rename = "$unflatten=IsTruncated", default)]
+    is_truncated: bool,
+    #[serde(rename = "$unflatten=MaxKeys", default)]
+    max_keys: u64,
+    /// Only returned when listing with a delimiter
+    #[serde(rename = "$unflatten=NextMarker")]
+    next_marker: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+impl From<ListBucketResultV1> for ListBucketResult {
+    /// Convert to the v2 shape, with the marker for the next page as `next_token`.
+    fn from(v1: ListBucketResultV1) -> Self {
+        let next_token = if v1.is_truncated {
+            v1.next_marker
+                .or_else(|| v1.contents.last().map(|c| c.key.clone()))
+        } else {
+            None
+        };
+
+        ListBucketResult {
+            key_count: v1.contents.len() as u64,
+            contents: v1.contents,
+            max_keys: v1.max_keys,
+            is_truncated: v1.is_truncated,
+            next_token,
+            common_prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Which ListObjects protocol to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListObjectsVersion {
+    /// `marker` based listing, for older S3-compatible gateways
+    V1,
+    /// `list-type=2` with continuation tokens, used by default
+    V2,
+}
+
+/// The result of listing with a delimiter: the objects directly under the
+/// prefix, and the "directories" below it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DelimitedListing {
+    pub objects: Vec<Contents>,
+    /// Prefixes up to and including the next delimiter, e.g. `logs/2023/`
+    pub common_prefixes: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Contents {
     #[serde(rename = "$unflatten=Key")]
     pub key: String,
     #[serde(rename = "$unflatten=LastModified")]
     pub last_modified: String,
-    #[serde(rename = "$unflatten=ETag")]
+    /// Unquoted ETag
+    #[serde(rename = "$unflatten=ETag", deserialize_with = "deserialize_etag")]
     pub etag: String,
     #[serde(rename = "$unflatten=Size")]
     pub size: u64,
@@ -85,38 +160,275 @@ pub struct Contents {
     pub storage_class: String,
 }
 
+/// Default overall timeout for control-plane requests (listing, deletes, metadata)
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Default timeout for establishing a connection to the endpoint
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default overall timeout for object downloads, which may stream large bodies
+pub const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Default overall timeout for object and part uploads, which may send large bodies
+pub const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Default delay before the first retry of a failed request, doubled for each
+/// retry after it
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Identifiers COS assigns to each request, which IBM support asks for when
+/// diagnosing problems.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RequestContext {
+    /// From the `x-amz-request-id` header, or `x-clv-request-id`
+    pub request_id: Option<String>,
+    /// From the `x-amz-id-2` header
+    pub host_id: Option<String>,
+}
+
+impl RequestContext {
+    pub(crate) fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        Self {
+            request_id: get("x-amz-request-id").or_else(|| get("x-clv-request-id")),
+            host_id: get("x-amz-id-2"),
+        }
+    }
+}
+
+impl Contents {
+    /// The object's storage class, if it is one of the known classes.
+    pub fn parsed_storage_class(&self) -> Option<StorageClass> {
+        self.storage_class.parse().ok()
+    }
+
+    /// `last_modified` parsed as an RFC 3339 timestamp.
+    pub fn last_modified_at(&self) -> Result<DateTime<Utc>, chrono::ParseError> {
+        parse_timestamp(&self.last_modified)
+    }
+}
+
+/// One line of a listing: last modified date, size in bytes and key.
+impl fmt::Display for Contents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:>10} {}", self.last_modified, self.size, self.key)
+    }
+}
+
+/// Creation date and name.
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.creation_date, self.name)
+    }
+}
+
+/// Filters for listings, e.g.
+/// `client.list_objects(bucket, None, None).filter_storage_class(StorageClass::ColdVault)`.
+///
+/// Objects whose `last_modified` can't be parsed never match a date filter.
+pub trait ContentsFilter: Iterator<Item = Contents> + Sized {
+    fn filter_storage_class(self, class: StorageClass) -> impl Iterator<Item = Contents> {
+        self.filter(move |c| c.parsed_storage_class() == Some(class))
+    }
+
+    /// Keep objects whose size in bytes is within `range`, e.g. `1024..`.
+    fn filter_size<R: RangeBounds<u64>>(self, range: R) -> impl Iterator<Item = Contents> {
+        self.filter(move |c| range.contains(&c.size))
+    }
+
+    fn filter_modified_before(self, date: DateTime<Utc>) -> impl Iterator<Item = Contents> {
+        self.filter(move |c| c.last_modified_at().is_ok_and(|m| m < date))
+    }
+
+    fn filter_modified_after(self, date: DateTime<Utc>) -> impl Iterator<Item = Contents> {
+        self.filter(move |c| c.last_modified_at().is_ok_and(|m| m > date))
+    }
+}
+
+impl<I: Iterator<Item = Contents>> ContentsFilter for I {}
+
+impl Bucket {
+    /// `creation_date` parsed as an RFC 3339 timestamp.
+    pub fn created_at(&self) -> Result<DateTime<Utc>, chrono::ParseError> {
+        parse_timestamp(&self.creation_date)
+    }
+}
+
+pub(crate) fn parse_timestamp(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(s).map(|d| d.with_timezone(&Utc))
+}
+
+/// A COS client authenticated with IAM tokens.
+///
+/// `Client` is `Send + Sync` and cheap to clone: clones share the token
+/// provider and the HTTP connection pool, so one client can serve many threads.
+#[derive(Clone)]
 pub struct Client {
-    pub(crate) tm: Arc<TokenManager>,
+    /// Applied to every request as it is sent
+    pub(crate) auth: Auth,
+    pub(crate) scheme: &'static str,
     pub(crate) endpoint: String,
+    /// Builds requests; they're sent through `transport`
     pub(crate) client: reqwest::blocking::Client,
+    pub(crate) transport: Arc<dyn Transport>,
+    pub(crate) download_timeout: Option<Duration>,
+    pub(crate) upload_timeout: Option<Duration>,
+    /// Retries of requests that fail with a server error or can't connect
+    pub(crate) max_retries: u32,
+    pub(crate) retry_backoff: Duration,
+    pub(crate) addressing_style: AddressingStyle,
+    /// Sent as `ibm-service-instance-id` by the calls that need it
+    pub(crate) instance_id: Option<String>,
 }
 
 impl Client {
-    pub fn new(tm: Arc<TokenManager>, endpoint: &str) -> Self {
+    pub fn new(tm: Arc<dyn CredentialProvider>, endpoint: &str) -> Self {
+        Self::with_http_client(tm, endpoint, reqwest::blocking::Client::new())
+    }
+
+    /// Create a client that sends requests through an existing `reqwest` client,
+    /// sharing its connection pool and configuration (timeouts, proxies, TLS).
+    pub fn with_http_client(
+        tm: Arc<dyn CredentialProvider>,
+        endpoint: &str,
+        client: reqwest::blocking::Client,
+    ) -> Self {
+        Self::with_auth(Auth::Bearer(tm), endpoint, client)
+    }
+
+    pub(crate) fn with_auth(auth: Auth, endpoint: &str, client: reqwest::blocking::Client) -> Self {
         Self {
-            tm: tm,
+            auth,
+            scheme: "https",
             endpoint: endpoint.to_string(),
-            client: reqwest::blocking::Client::new(),
+            transport: Arc::new(client.clone()),
+            client,
+            download_timeout: None,
+            upload_timeout: None,
+            max_retries: 0,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            addressing_style: AddressingStyle::VirtualHosted,
+            instance_id: None,
         }
     }
 
-    pub fn list_buckets(&self, instance_id: &str) -> Result<Vec<Bucket>, Error> {
+    /// Create a client that sends its requests through `transport`, e.g. one
+    /// serving canned responses in tests.
+    pub fn with_transport(
+        tm: Arc<dyn CredentialProvider>,
+        endpoint: &str,
+        transport: Arc<dyn Transport>,
+    ) -> Self {
+        Self {
+            transport,
+            ..Self::with_http_client(tm, endpoint, reqwest::blocking::Client::new())
+        }
+    }
+
+    /// Create a client that sends no credentials, for reading public buckets.
+    ///
+    /// Operations that need permissions beyond public read fail with an access
+    /// denied error from COS.
+    pub fn anonymous(endpoint: &str) -> Self {
+        Self::with_auth(Auth::Anonymous, endpoint, reqwest::blocking::Client::new())
+    }
+
+    /// Start building a client with configurable timeouts.
+    ///
+    /// Unless overridden, requests time out after [`DEFAULT_TIMEOUT`], connecting
+    /// times out after [`DEFAULT_CONNECT_TIMEOUT`], object downloads time out
+    /// after [`DEFAULT_DOWNLOAD_TIMEOUT`] and uploads after [`DEFAULT_UPLOAD_TIMEOUT`].
+    pub fn builder(tm: Arc<dyn CredentialProvider>, endpoint: &str) -> ClientBuilder {
+        ClientBuilder::new(tm, endpoint)
+    }
+
+    /// Use `instance_id` for calls that need a service instance when none is
+    /// passed, e.g. `list_buckets(None)`.
+    pub fn with_instance_id(mut self, instance_id: &str) -> Self {
+        self.instance_id = Some(instance_id.to_string());
+        self
+    }
+
+    /// `instance_id`, or else the one set with `with_instance_id`.
+    pub(crate) fn resolve_instance_id<'a>(
+        &'a self,
+        instance_id: Option<&'a str>,
+    ) -> Result<&'a str, Error> {
+        instance_id.or(self.instance_id.as_deref()).ok_or_else(|| {
+            CosError::Validation("no service instance id set, see with_instance_id".to_string())
+                .into()
+        })
+    }
+
+    /// The COS endpoint host this client sends requests to, without a scheme.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The URL of `bucket` in this client's addressing style, ending in `/`.
+    pub fn bucket_url(&self, bucket: &str) -> String {
+        self.addressing_style
+            .bucket_url(self.scheme, &self.endpoint, bucket)
+    }
+
+    /// Send an authorized request to `path` on the endpoint and return the
+    /// response body, for COS APIs this crate does not wrap yet.
+    ///
+    /// `path` is taken as is, e.g. `bucket/?analytics`, whatever the
+    /// addressing style. This is a low-level escape hatch and may change.
+    #[instrument(skip_all, fields(path = path))]
+    pub fn get_raw(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<String, Error> {
+        let url = format!(
+            "{}://{}/{}",
+            self.scheme,
+            self.endpoint,
+            path.trim_start_matches('/')
+        );
+
+        let response = self
+            .client
+            .request(method, url)
+            .query(query)
+            .send_via(self)?;
+
+        read_checked(response)
+    }
+
+    /// List the buckets of a service instance; `None` uses the one set with
+    /// `with_instance_id`.
+    pub fn list_buckets<'a>(
+        &'a self,
+        instance_id: impl Into<Option<&'a str>>,
+    ) -> Result<Vec<Bucket>, Error> {
+        Ok(self.list_buckets_with_owner(instance_id)?.1)
+    }
+
+    /// Like `list_buckets`, also returning the owner of the buckets.
+    #[instrument(skip_all)]
+    pub fn list_buckets_with_owner<'a>(
+        &'a self,
+        instance_id: impl Into<Option<&'a str>>,
+    ) -> Result<(Owner, Vec<Bucket>), Error> {
+        let instance_id = self.resolve_instance_id(instance_id.into())?;
         let c = &self.client;
 
-        let url = format!("https://{}/", self.endpoint);
+        let url = format!("{}://{}/", self.scheme, self.endpoint);
         let response = c
             .get(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
             .header("ibm-service-instance-id", instance_id.to_string())
-            .send()?;
+            .send_via(self)?;
 
-        let text: String = check_response(response)?.text()?;
+        let text = read_checked(response)?;
         let bucket_resp: ListAllMyBucketsResult = from_str(&text)?;
 
-        Ok(bucket_resp.buckets.list)
+        Ok((bucket_resp.owner, bucket_resp.buckets.list))
     }
 
     pub fn list_objects(
@@ -124,146 +436,418 @@ impl Client {
         bucket: &str,
         prefix: Option<String>,
         start_after: Option<String>,
-    ) -> ObjectIterator {
+    ) -> ObjectIterator<'_> {
         ObjectIterator::new(self, bucket, prefix.clone(), start_after.clone())
     }
 
+    /// Like `list_objects`, using the version 1 protocol (`marker`/`NextMarker`)
+    /// for gateways that don't support `list-type=2`.
+    ///
+    /// Listing starts after `marker` when given.
+    pub fn list_objects_v1(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+        marker: Option<String>,
+    ) -> ObjectIterator<'_> {
+        let mut it = ObjectIterator::new(self, bucket, prefix, None);
+        it.version = ListObjectsVersion::V1;
+        it.continuation_token = marker;
+        it
+    }
+
+    fn _list_objects_v1(
+        &self,
+        bucket: &str,
+        prefix: &Option<String>,
+        marker: &Option<String>,
+    ) -> Result<ListBucketResult, Error> {
+        let c = &self.client;
+
+        let mut url = reqwest::Url::parse(&self.bucket_url(bucket))?;
+
+        let mut query = vec![];
+        if let Some(pre) = prefix {
+            query.push(format!("prefix={}", encode(pre)));
+        }
+        if let Some(m) = marker {
+            query.push(format!("marker={}", encode(m)));
+        }
+        if !query.is_empty() {
+            url.set_query(Some(&query.join("&")));
+        }
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let page: ListBucketResultV1 = from_str(&text)?;
+        Ok(page.into())
+    }
+
+    /// Fetch a single page of a listing, for callers doing their own pagination.
+    ///
+    /// Pass the previous page's `next_token` as `continuation_token` to get the
+    /// following page. `max_keys` caps the page size (COS's default and limit is 1000).
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn list_objects_page(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+        continuation_token: Option<String>,
+        max_keys: Option<u32>,
+    ) -> Result<ListBucketResult, Error> {
+        self._list_objects(bucket, &prefix, &continuation_token, &None, &None, max_keys)
+    }
+
+    /// List the objects under `prefix`, grouping keys that contain `delimiter`
+    /// after the prefix into common prefixes, like a directory listing.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn list_objects_delimited(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+        delimiter: &str,
+    ) -> Result<DelimitedListing, Error> {
+        let delimiter = Some(delimiter.to_string());
+        let mut listing = DelimitedListing::default();
+        let mut continuation_token = None;
+
+        loop {
+            let page = self._list_objects(
+                bucket,
+                &prefix,
+                &continuation_token,
+                &None,
+                &delimiter,
+                None,
+            )?;
+
+            listing.objects.extend(page.contents);
+            listing
+                .common_prefixes
+                .extend(page.common_prefixes.into_iter().map(|p| p.prefix));
+
+            match page.next_token {
+                Some(tok) => continuation_token = Some(tok),
+                None => return Ok(listing),
+            }
+        }
+    }
+
     fn _list_objects(
         &self,
         bucket: &str,
         prefix: &Option<String>,
         continuation_token: &Option<String>,
         start_after: &Option<String>,
+        delimiter: &Option<String>,
+        max_keys: Option<u32>,
     ) -> Result<ListBucketResult, Error> {
         let c = &self.client;
 
         let url = build_list_objects_url(
-            &self.endpoint,
-            bucket,
+            &self.bucket_url(bucket),
             prefix,
             continuation_token,
             start_after,
+            delimiter,
+            max_keys,
         )?;
 
-        let response = c
-            .get(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .send()?;
+        let response = c.get(url).send_via(self)?;
 
-        let text: String = check_response(response)?.text()?;
+        let text = read_checked(response)?;
         let objlist: ListBucketResult = from_str(&text)?;
         Ok(objlist)
     }
 
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
     pub fn get_object_at_range(
         &self,
         bucket: &str,
         key: &str,
         start: u64,
         end: Option<u64>,
-    ) -> Result<Box<dyn Read>, Error> {
+    ) -> Result<GetObjectRangeOutput, Error> {
         let c = &self.client;
-        let url = format!("https://{}.{}/{}", bucket, self.endpoint, key);
+        let url = format!("{}{}", self.bucket_url(bucket), key);
 
         let mut end_str = "".to_string();
         if let Some(e) = end {
             end_str = format!("{}", e);
         }
 
-        let response = c
+        let mut req = c
             .get(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .header("Range", format!("bytes={}-{}", start, end_str))
-            .send()?;
+            .header("Range", format!("bytes={}-{}", start, end_str));
+
+        if let Some(t) = self.download_timeout {
+            req = req.timeout(t);
+        }
+
+        let response = req.send_via(self)?;
 
         let r = check_response(response)?;
-        Ok(Box::new(r))
+        let range = r
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+
+        Ok(GetObjectRangeOutput {
+            range,
+            body: Box::new(r),
+        })
     }
 
+    /// Stream an object's body as stored: objects uploaded with
+    /// `Content-Encoding: gzip` are returned compressed.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
     pub fn get_object(&self, bucket: &str, key: &str) -> Result<Box<dyn Read>, Error> {
         let c = &self.client;
-        let url = format!("https://{}.{}/{}", bucket, self.endpoint, key);
+        let url = format!("{}{}", self.bucket_url(bucket), key);
 
-        let response = c
-            .get(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .send()?;
+        let mut req = c.get(url);
+
+        if let Some(t) = self.download_timeout {
+            req = req.timeout(t);
+        }
+
+        let response = req.send_via(self)?;
 
         let r = check_response(response)?;
         Ok(Box::new(r))
     }
 
+    /// Upload `body` in a single PUT, of at most
+    /// [`MAX_PUT_SIZE`](crate::object::MAX_PUT_SIZE) bytes.
     pub fn put_object<B: Into<reqwest::blocking::Body>>(
         &self,
         bucket: &str,
         key: &str,
         body: B,
-    ) -> Result<(), Error> {
-        let c = &self.client;
-        let url = format!("https://{}.{}/{}", bucket, self.endpoint, key);
-
-        let response = c
-            .put(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .body(body)
-            .send()?;
+    ) -> Result<PutObjectOutput, Error> {
+        self.put_object_with_options(bucket, key, body, &PutObjectOptions::default())
+    }
 
-        let _r = check_response(response)?;
+    pub fn delete_object(&self, bucket: &str, key: &str) -> Result<(), Error> {
+        self.delete_object_version(bucket, key, None)?;
         Ok(())
     }
+}
 
-    pub fn delete_object(&self, bucket: &str, key: &str) -> Result<(), Error> {
-        let c = &self.client;
-        let url = format!("https://{}.{}/{}", bucket, self.endpoint, key);
+pub struct ClientBuilder {
+    auth: Auth,
+    endpoint: String,
+    http: reqwest::blocking::ClientBuilder,
+    download_timeout: Duration,
+    upload_timeout: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+    addressing_style: AddressingStyle,
+    instance_id: Option<String>,
+    decompress: bool,
+}
 
-        let response = c
-            .delete(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .send()?;
+impl ClientBuilder {
+    pub fn new(tm: Arc<dyn CredentialProvider>, endpoint: &str) -> Self {
+        Self::with_auth(Auth::Bearer(tm), endpoint)
+    }
 
-        check_response(response)?;
-        Ok(())
+    /// Build a client without credentials, like `Client::anonymous`.
+    pub fn anonymous(endpoint: &str) -> Self {
+        Self::with_auth(Auth::Anonymous, endpoint)
+    }
+
+    fn with_auth(auth: Auth, endpoint: &str) -> Self {
+        Self {
+            auth,
+            endpoint: endpoint.to_string(),
+            http: reqwest::blocking::Client::builder()
+                .timeout(DEFAULT_TIMEOUT)
+                .connect_timeout(DEFAULT_CONNECT_TIMEOUT),
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+            max_retries: 0,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            addressing_style: AddressingStyle::VirtualHosted,
+            instance_id: None,
+            decompress: false,
+        }
+    }
+
+    /// Overall timeout for each request, from connecting until the body is read.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.http = self.http.timeout(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.http = self.http.connect_timeout(timeout);
+        self
+    }
+
+    /// Route requests through a proxy, e.g. `reqwest::Proxy::https(url)?.basic_auth(user, pass)`.
+    ///
+    /// May be called more than once; proxies are tried in the order they were added.
+    /// Without an explicit proxy the `HTTP_PROXY`/`HTTPS_PROXY` environment variables apply.
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.http = self.http.proxy(proxy);
+        self
+    }
+
+    /// Ignore any proxy configured through the environment.
+    pub fn no_proxy(mut self) -> Self {
+        self.http = self.http.no_proxy();
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. an internal CA loaded with
+    /// `reqwest::Certificate::from_pem` or `reqwest::Certificate::from_der`.
+    pub fn add_root_certificate(mut self, cert: reqwest::Certificate) -> Self {
+        self.http = self.http.add_root_certificate(cert);
+        self
+    }
+
+    /// Disable TLS certificate validation entirely.
+    ///
+    /// Only intended for test environments; any certificate, including expired
+    /// or self-signed ones, will be accepted.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.http = self.http.danger_accept_invalid_certs(accept);
+        self
+    }
+
+    /// Overall timeout for `get_object` and `get_object_at_range`, replacing
+    /// the regular request timeout for those calls.
+    pub fn download_timeout(mut self, timeout: Duration) -> Self {
+        self.download_timeout = timeout;
+        self
+    }
+
+    /// Overall timeout for `put_object` and `upload_part` and the uploads built
+    /// on them, replacing the regular request timeout for those calls.
+    pub fn upload_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_timeout = timeout;
+        self
+    }
+
+    /// Retry a request up to `max_retries` times when COS answers with a
+    /// server error or asks to slow down (429, 503 `SlowDown`), or when the
+    /// connection fails. Off by default.
+    ///
+    /// Requests with a streamed body are sent only once, as it can't be replayed.
+    pub fn retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled for each retry after it;
+    /// [`DEFAULT_RETRY_BACKOFF`] by default.
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Put bucket names in the hostname (the default) or in the URL path.
+    pub fn addressing_style(mut self, style: AddressingStyle) -> Self {
+        self.addressing_style = style;
+        self
+    }
+
+    /// Service instance for calls that need one when none is passed.
+    pub fn instance_id(mut self, instance_id: &str) -> Self {
+        self.instance_id = Some(instance_id.to_string());
+        self
+    }
+
+    /// Whether to decode response bodies by their `Content-Encoding`. Off by
+    /// default, so objects are returned byte for byte as stored.
+    ///
+    /// `gzip` and `br` encodings are decoded, and requests advertise them in
+    /// `Accept-Encoding`.
+    pub fn decompress(mut self, on: bool) -> Self {
+        self.decompress = on;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let http = self
+            .http
+            .gzip(self.decompress)
+            .brotli(self.decompress)
+            .build()?;
+
+        let mut client = Client::with_auth(self.auth, &self.endpoint, http);
+        client.download_timeout = Some(self.download_timeout);
+        client.upload_timeout = Some(self.upload_timeout);
+        client.max_retries = self.max_retries;
+        client.retry_backoff = self.retry_backoff;
+        client.addressing_style = self.addressing_style;
+        client.instance_id = self.instance_id;
+        Ok(client)
     }
 }
 
 pub(crate) fn check_response(
     response: reqwest::blocking::Response,
 ) -> Result<reqwest::blocking::Response, Error> {
-    if !response.status().is_success() {
-        return Err(format!(
-            "request failed: code='{}' body='{:?}'",
-            response.status(),
-            response.text().unwrap()
-        )
-        .into());
+    match response.status() {
+        s if s.is_success() => {}
+        StatusCode::NOT_MODIFIED => return Err(CosError::NotModified.into()),
+        StatusCode::PRECONDITION_FAILED => {
+            return Err(
+                CosError::PreconditionFailed(Box::new(ApiError::from_response(response))).into(),
+            )
+        }
+        _ => return Err(CosError::Api(Box::new(ApiError::from_response(response))).into()),
     }
 
     Ok(response)
 }
 
+/// `check_response` and read the body text, as most operations parse it.
+pub(crate) fn read_checked(response: reqwest::blocking::Response) -> Result<String, Error> {
+    Ok(check_response(response)?.text()?)
+}
+
+/// Like `read_checked`, but also failing when a
+/// 200 response carries an `<Error>` document, as completing a multipart
+/// upload or copying an object can.
+pub(crate) fn check_response_text(response: reqwest::blocking::Response) -> Result<String, Error> {
+    let r = check_response(response)?;
+    let status = r.status();
+    let context = RequestContext::from_headers(r.headers());
+    let text = r.text()?;
+
+    if is_error_document(&text) {
+        let mut e = ApiError::new(status, text);
+        e.context = context;
+        return Err(CosError::Api(Box::new(e)).into());
+    }
+
+    Ok(text)
+}
+
+fn is_error_document(text: &str) -> bool {
+    let mut rest = text.trim_start();
+    if rest.starts_with("<?xml") {
+        rest = rest.find("?>").map_or("", |i| rest[i + 2..].trim_start());
+    }
+    rest.starts_with("<Error>") || rest.starts_with("<Error ")
+}
+
 pub struct ObjectIterator<'a> {
     client: &'a Client,
     bucket: String,
     prefix: Option<String>,
     continuation_token: Option<String>,
     start_after: Option<String>,
+    version: ListObjectsVersion,
     results: VecDeque<Contents>,
     complete: bool,
+    /// Pages fetched so far
+    pages: u32,
 }
 
 impl<'a> ObjectIterator<'a> {
@@ -276,44 +860,91 @@ impl<'a> ObjectIterator<'a> {
         Self {
             client,
             bucket: bucket.to_string(),
-            prefix: prefix,
+            prefix,
             continuation_token: None,
-            start_after: start_after,
+            start_after,
+            version: ListObjectsVersion::V2,
             results: VecDeque::new(),
             complete: false,
+            pages: 0,
         }
     }
+
+    /// Start from a token saved with `continuation_token`, to resume a listing.
+    pub fn with_continuation_token(mut self, token: &str) -> Self {
+        self.continuation_token = Some(token.to_string());
+        self
+    }
+
+    /// The opaque token of the next page to fetch, or `None` once the last
+    /// page has been fetched.
+    ///
+    /// The keys of the current page that are still `buffered` come before
+    /// that page, so save the token when `buffered()` is 0 to resume without
+    /// skipping any keys.
+    pub fn continuation_token(&self) -> Option<&str> {
+        if self.complete {
+            return None;
+        }
+        self.continuation_token.as_deref()
+    }
+
+    /// Keys fetched with the current page but not yet returned.
+    pub fn buffered(&self) -> usize {
+        self.results.len()
+    }
 }
 
 impl Iterator for ObjectIterator<'_> {
     type Item = Contents;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.results.len() < 1 {
+        // a page may hold no keys while more pages follow
+        while self.results.is_empty() {
             if self.complete {
                 return None;
             }
 
-            match self.client._list_objects(
-                &self.bucket,
-                &self.prefix,
-                &self.continuation_token,
-                &self.start_after,
-            ) {
-                Ok(mut v) => {
-                    if v.contents.len() < 1 {
-                        // empty bucket
-                        self.complete = true;
-                        return None;
-                    }
+            self.pages += 1;
+            let span = debug_span!(
+                "list_objects_page",
+                bucket = self.bucket.as_str(),
+                page = self.pages,
+                keys = tracing::field::Empty
+            );
+            let _guard = span.enter();
 
-                    for o in v.contents.drain(..) {
-                        self.results.push_back(o);
-                    }
-                    if v.next_token.is_some() {
-                        self.continuation_token = v.next_token;
-                    } else {
-                        self.complete = true;
+            let page = match self.version {
+                ListObjectsVersion::V1 => self.client._list_objects_v1(
+                    &self.bucket,
+                    &self.prefix,
+                    &self.continuation_token,
+                ),
+                ListObjectsVersion::V2 => self.client._list_objects(
+                    &self.bucket,
+                    &self.prefix,
+                    &self.continuation_token,
+                    &self.start_after,
+                    &None,
+                    None,
+                ),
+            };
+
+            match page {
+                Ok(v) => {
+                    span.record("keys", v.contents.len());
+                    self.results.extend(v.contents);
+
+                    match v.next_token {
+                        Some(token) if v.is_truncated => self.continuation_token = Some(token),
+                        None if v.is_truncated => {
+                            error!(
+                                "listing of {} is truncated but has no continuation token",
+                                self.bucket
+                            );
+                            self.complete = true;
+                        }
+                        _ => self.complete = true,
                     }
                 }
                 Err(e) => {
@@ -323,31 +954,45 @@ impl Iterator for ObjectIterator<'_> {
             }
         }
 
-        Some(self.results.pop_front().unwrap())
+        self.results.pop_front()
     }
 }
 
 fn build_list_objects_url(
-    endpoint: &str,
-    bucket: &str,
+    bucket_url: &str,
     prefix: &Option<String>,
     continuation_token: &Option<String>,
     start_after: &Option<String>,
+    delimiter: &Option<String>,
+    max_keys: Option<u32>,
 ) -> Result<reqwest::Url, Error> {
-    let mut url = reqwest::Url::parse(&format!("https://{}.{}/?list-type=2", bucket, endpoint))?;
+    let mut url = reqwest::Url::parse(bucket_url)?;
+
+    // values are percent-encoded by hand since form encoding turns spaces into '+'
+    let mut query = vec!["list-type=2".to_string()];
 
     if let Some(tok) = continuation_token {
-        url.query_pairs_mut().append_pair("continuation-token", tok);
+        query.push(format!("continuation-token={}", encode(tok)));
     }
 
     if let Some(pre) = prefix {
-        url.query_pairs_mut().append_pair("prefix", pre);
+        query.push(format!("prefix={}", encode(pre)));
     }
 
     if let Some(after) = start_after {
-        url.query_pairs_mut().append_pair("start-after", after);
+        query.push(format!("start-after={}", encode(after)));
+    }
+
+    if let Some(delim) = delimiter {
+        query.push(format!("delimiter={}", encode(delim)));
+    }
+
+    if let Some(max) = max_keys {
+        query.push(format!("max-keys={}", max));
     }
 
+    url.set_query(Some(&query.join("&")));
+
     Ok(url)
 }
 
@@ -394,21 +1039,24 @@ mod tests {
             contents: vec![],
             key_count: 0,
             max_keys: 1000,
+            is_truncated: false,
             next_token: None,
+            common_prefixes: vec![],
         };
 
-        let objs: ListBucketResult = from_str(&input).unwrap();
+        let objs: ListBucketResult = from_str(input).unwrap();
         assert_eq!(objs, exp);
     }
 
     #[test]
     fn test_build_list_objects_url() {
         let res = build_list_objects_url(
-            "cos.cloud.ibm.com",
-            "test-bucket-123",
+            "https://test-bucket-123.cos.cloud.ibm.com/",
             &None,
             &None,
             &Some("object-key/with/special=characters+001.stuff".to_string()),
+            &None,
+            None,
         );
 
         let mut url = reqwest::Url::parse("https://test-bucket-123.cos.cloud.ibm.com/").unwrap();
@@ -421,4 +1069,409 @@ mod tests {
 
         assert_eq!(res.unwrap(), url);
     }
+
+    #[test]
+    fn test_build_list_objects_url_encodes_values() {
+        let res = build_list_objects_url(
+            "https://test-bucket-123.cos.cloud.ibm.com/",
+            &Some("reports/2023 Q1/".to_string()),
+            &Some("1a+b/c==".to_string()),
+            &None,
+            &Some("/".to_string()),
+            Some(100),
+        )
+        .unwrap();
+
+        assert_eq!(
+            res.as_str(),
+            "https://test-bucket-123.cos.cloud.ibm.com/?list-type=2&continuation-token=1a%2Bb%2Fc%3D%3D&prefix=reports%2F2023%20Q1%2F&delimiter=%2F&max-keys=100"
+        );
+    }
+
+    #[test]
+    fn test_list_objects_common_prefixes() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>logbase</Name><Prefix>logs/</Prefix><KeyCount>3</KeyCount><MaxKeys>1000</MaxKeys><Delimiter>/</Delimiter><IsTruncated>false</IsTruncated><Contents><Key>logs/index.txt</Key><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>"abc"</ETag><Size>10</Size><StorageClass>STANDARD</StorageClass></Contents><CommonPrefixes><Prefix>logs/2022/</Prefix></CommonPrefixes><CommonPrefixes><Prefix>logs/2023/</Prefix></CommonPrefixes></ListBucketResult>"#;
+
+        let objs: ListBucketResult = from_str(input).unwrap();
+        assert_eq!(objs.contents.len(), 1);
+        assert_eq!(objs.contents[0].etag, "abc");
+        assert_eq!(
+            objs.common_prefixes
+                .iter()
+                .map(|p| p.prefix.as_str())
+                .collect::<Vec<_>>(),
+            vec!["logs/2022/", "logs/2023/"]
+        );
+    }
+
+    #[test]
+    fn test_list_objects_paginates() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let page = crate::transport::mock::listing;
+
+        transport
+            .respond(200, &page(&["a", "b"], Some("tok+1")))
+            .respond(200, &page(&[], Some("tok+2")))
+            .respond(200, &page(&["c"], None));
+
+        let keys: Vec<String> = client
+            .list_objects("bucket", Some("p/".to_string()), None)
+            .map(|c| c.key)
+            .collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].url,
+            "https://bucket.s3.example.com/?list-type=2&prefix=p%2F"
+        );
+        assert_eq!(
+            requests[1].url,
+            "https://bucket.s3.example.com/?list-type=2&continuation-token=tok%2B1&prefix=p%2F"
+        );
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+    }
+
+    #[test]
+    fn test_resume_listing_from_token() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let page = crate::transport::mock::listing;
+
+        transport
+            .respond(200, &page(&["a", "b"], Some("tok")))
+            .respond(200, &page(&["c", "d"], None))
+            .respond(200, &page(&["c", "d"], None));
+
+        let mut it = client.list_objects("bucket", None, None);
+        assert_eq!(it.continuation_token(), None);
+        it.next();
+        it.next();
+        assert_eq!(it.buffered(), 0);
+        let token = it.continuation_token().unwrap().to_string();
+        assert_eq!(token, "tok");
+        let rest: Vec<String> = it.map(|c| c.key).collect();
+
+        let resumed: Vec<String> = client
+            .list_objects("bucket", None, None)
+            .with_continuation_token(&token)
+            .map(|c| c.key)
+            .collect();
+        assert_eq!(resumed, rest);
+        assert_eq!(
+            transport.requests()[2].url,
+            "https://bucket.s3.example.com/?list-type=2&continuation-token=tok"
+        );
+    }
+
+    #[test]
+    fn test_anonymous_client_sends_no_credentials() {
+        let transport = Arc::new(crate::transport::mock::MockTransport::default());
+        let mut client = Client::anonymous("s3.example.com");
+        client.transport = transport.clone();
+
+        transport.respond(200, "data");
+        client.get_object("public", "k").unwrap();
+
+        assert!(!transport.requests()[0]
+            .headers
+            .contains_key("authorization"));
+    }
+
+    #[test]
+    fn test_anonymous_builder() {
+        let client = ClientBuilder::anonymous("s3.example.com")
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert!(matches!(client.auth, Auth::Anonymous));
+        assert_eq!(client.download_timeout, Some(DEFAULT_DOWNLOAD_TIMEOUT));
+        assert_eq!(client.upload_timeout, Some(DEFAULT_UPLOAD_TIMEOUT));
+    }
+
+    #[test]
+    fn test_iterate_empty_bucket() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, "<ListBucketResult><KeyCount>0</KeyCount><MaxKeys>1000</MaxKeys><IsTruncated>false</IsTruncated></ListBucketResult>");
+
+        assert_eq!(client.list_objects("empty", None, None).count(), 0);
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_list_objects_page() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, "<ListBucketResult><KeyCount>0</KeyCount><MaxKeys>5</MaxKeys><IsTruncated>true</IsTruncated><NextContinuationToken>next</NextContinuationToken></ListBucketResult>");
+
+        let page = client
+            .list_objects_page("bucket", None, Some("prev".to_string()), Some(5))
+            .unwrap();
+        assert_eq!(page.max_keys, 5);
+        assert!(page.is_truncated);
+        assert_eq!(page.next_token.as_deref(), Some("next"));
+
+        assert_eq!(
+            transport.requests()[0].url,
+            "https://bucket.s3.example.com/?list-type=2&continuation-token=prev&max-keys=5"
+        );
+    }
+
+    #[test]
+    fn test_path_style_urls() {
+        let (mut client, transport) = crate::transport::mock::client();
+        client.addressing_style = AddressingStyle::Path;
+        transport.respond(
+            200,
+            "<ListBucketResult><KeyCount>0</KeyCount><MaxKeys>5</MaxKeys></ListBucketResult>",
+        );
+
+        client
+            .list_objects_page("bucket", None, None, None)
+            .unwrap();
+
+        assert_eq!(
+            transport.requests()[0].url,
+            "https://s3.example.com/bucket/?list-type=2"
+        );
+        assert_eq!(client.endpoint(), "s3.example.com");
+        assert_eq!(
+            client.bucket_url("bucket"),
+            "https://s3.example.com/bucket/"
+        );
+    }
+
+    #[test]
+    fn test_list_objects_v1() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let entry = |key: &str| crate::transport::mock::contents(key, "abc", 1);
+        transport
+            .respond(200, &format!("<ListBucketResult><Name>bucket</Name><Prefix>p/</Prefix><Marker></Marker><MaxKeys>2</MaxKeys><IsTruncated>true</IsTruncated>{}{}</ListBucketResult>", entry("p/a"), entry("p/b c")))
+            .respond(200, &format!("<ListBucketResult><Name>bucket</Name><Prefix>p/</Prefix><Marker>p/b c</Marker><MaxKeys>2</MaxKeys><IsTruncated>false</IsTruncated>{}</ListBucketResult>", entry("p/d")));
+
+        let keys: Vec<String> = client
+            .list_objects_v1("bucket", Some("p/".to_string()), None)
+            .map(|c| c.key)
+            .collect();
+        assert_eq!(keys, vec!["p/a", "p/b c", "p/d"]);
+
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0].url,
+            "https://bucket.s3.example.com/?prefix=p%2F"
+        );
+        assert_eq!(
+            requests[1].url,
+            "https://bucket.s3.example.com/?prefix=p%2F&marker=p%2Fb%20c"
+        );
+    }
+
+    #[test]
+    fn test_client_shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Client>();
+
+        let (client, transport) = crate::transport::mock::client();
+        for _ in 0..4 {
+            transport.respond(200, "hello");
+        }
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let client = client.clone();
+                std::thread::spawn(move || {
+                    let mut body = String::new();
+                    client
+                        .get_object("bucket", &format!("key-{}", i))
+                        .unwrap()
+                        .read_to_string(&mut body)
+                        .unwrap();
+                    body
+                })
+            })
+            .collect();
+
+        for h in handles {
+            assert_eq!(h.join().unwrap(), "hello");
+        }
+        assert_eq!(transport.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_contents_filters() {
+        let contents = |key: &str, size: u64, modified: &str, class: &str| Contents {
+            key: key.to_string(),
+            last_modified: modified.to_string(),
+            etag: "abc".to_string(),
+            size,
+            storage_class: class.to_string(),
+        };
+        let listing = vec![
+            contents("old-cold", 10, "2022-01-01T00:00:00.000Z", "COLD"),
+            contents("old-big", 5000, "2022-06-01T00:00:00.000Z", "STANDARD"),
+            contents("new-small", 10, "2023-06-01T00:00:00.000Z", "STANDARD"),
+            contents("unparsed", 10, "yesterday", "COLD"),
+        ];
+
+        let keys = |it: &mut dyn Iterator<Item = Contents>| it.map(|c| c.key).collect::<Vec<_>>();
+        let cutoff = "2023-01-01T00:00:00Z".parse().unwrap();
+
+        assert_eq!(
+            keys(
+                &mut listing
+                    .clone()
+                    .into_iter()
+                    .filter_storage_class(StorageClass::ColdVault)
+            ),
+            vec!["old-cold", "unparsed"]
+        );
+        assert_eq!(
+            keys(
+                &mut listing
+                    .clone()
+                    .into_iter()
+                    .filter_modified_before(cutoff)
+                    .filter_size(..100)
+            ),
+            vec!["old-cold"]
+        );
+        assert_eq!(
+            keys(&mut listing.into_iter().filter_modified_after(cutoff)),
+            vec!["new-small"]
+        );
+    }
+
+    #[test]
+    fn test_display_listing_types() {
+        let obj = Contents {
+            key: "logs/a.txt".to_string(),
+            last_modified: "2023-01-01T00:00:00.000Z".to_string(),
+            etag: "abc".to_string(),
+            size: 1234,
+            storage_class: "STANDARD".to_string(),
+        };
+        assert_eq!(
+            obj.to_string(),
+            "2023-01-01T00:00:00.000Z       1234 logs/a.txt"
+        );
+
+        let bucket = Bucket {
+            name: "b".to_string(),
+            creation_date: "2023-04-05T06:07:08.123Z".to_string(),
+        };
+        assert_eq!(bucket.to_string(), "2023-04-05T06:07:08.123Z b");
+    }
+
+    #[test]
+    fn test_builder_decompress() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        // "hello", gzipped
+        const GZIPPED: [u8; 25] = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0x07, 0x00, 0x86, 0xa6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00,
+        ];
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-encoding: gzip\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    GZIPPED.len()
+                )
+                .unwrap();
+                stream.write_all(&GZIPPED).unwrap();
+            }
+        });
+
+        let get = |decompress: bool| {
+            let mut client = ClientBuilder::anonymous(&endpoint)
+                .addressing_style(AddressingStyle::Path)
+                .decompress(decompress)
+                .build()
+                .unwrap();
+            client.scheme = "http";
+
+            let mut body = Vec::new();
+            client
+                .get_object("b", "k")
+                .unwrap()
+                .read_to_end(&mut body)
+                .unwrap();
+            body
+        };
+
+        assert_eq!(get(false), GZIPPED);
+        assert_eq!(get(true), b"hello");
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let bucket = Bucket {
+            name: "b".to_string(),
+            creation_date: "2023-04-05T06:07:08.123Z".to_string(),
+        };
+        assert_eq!(
+            bucket.created_at().unwrap().to_rfc3339(),
+            "2023-04-05T06:07:08.123+00:00"
+        );
+
+        let bucket = Bucket {
+            creation_date: "yesterday".to_string(),
+            ..bucket
+        };
+        assert!(bucket.created_at().is_err());
+    }
+
+    #[test]
+    fn test_request_context_from_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(
+            RequestContext::from_headers(&headers),
+            RequestContext::default()
+        );
+
+        headers.insert("x-clv-request-id", "clv-123".parse().unwrap());
+        assert_eq!(
+            RequestContext::from_headers(&headers).request_id.as_deref(),
+            Some("clv-123")
+        );
+
+        headers.insert("x-amz-request-id", "amz-456".parse().unwrap());
+        headers.insert("x-amz-id-2", "host-789".parse().unwrap());
+        let ctx = RequestContext::from_headers(&headers);
+        assert_eq!(ctx.request_id.as_deref(), Some("amz-456"));
+        assert_eq!(ctx.host_id.as_deref(), Some("host-789"));
+    }
+
+    #[test]
+    fn test_get_raw() {
+        let (client, transport) = crate::transport::mock::client();
+
+        transport.respond(200, "<AnalyticsConfiguration/>");
+        let body = client
+            .get_raw(
+                reqwest::Method::GET,
+                "/bucket/",
+                &[("analytics", ""), ("id", "a b")],
+            )
+            .unwrap();
+        assert_eq!(body, "<AnalyticsConfiguration/>");
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].method, reqwest::Method::GET);
+        assert_eq!(
+            requests[0].url,
+            "https://s3.example.com/bucket/?analytics=&id=a+b"
+        );
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+    }
 }
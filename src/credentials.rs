@@ -0,0 +1,328 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ibmcloud_iam::token::{Token, TokenManager, DEFAULT_IAM_ENDPOINT};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::cos::Error;
+
+/// Attempts made to fetch a token before giving up
+const TOKEN_ATTEMPTS: u32 = 3;
+const TOKEN_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// A source of IAM bearer tokens for `cos::Client`.
+///
+/// Implemented by [`IamTokenProvider`], which fetches and refreshes tokens with
+/// an API key, and by `Token` itself, for a token obtained elsewhere.
+/// `ibmcloud_iam`'s `TokenManager` is supported too, but it panics when IAM
+/// can't be reached, so its failures are never retried.
+pub trait CredentialProvider: Send + Sync {
+    fn token(&self) -> Result<Token, Error>;
+
+    /// Get a fresh token after COS rejected the current one.
+    ///
+    /// Providers that cache tokens should fetch a new one here; the default
+    /// just calls `token`.
+    fn refresh(&self) -> Result<Token, Error> {
+        self.token()
+    }
+}
+
+/// Fetch a token from `provider`, retrying transient failures with a growing delay.
+pub(crate) fn fetch_token(
+    provider: &dyn CredentialProvider,
+    refresh: bool,
+) -> Result<Token, Error> {
+    let mut delay = TOKEN_RETRY_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        let res = if refresh {
+            provider.refresh()
+        } else {
+            provider.token()
+        };
+
+        match res {
+            Err(e) if attempt < TOKEN_ATTEMPTS && is_transient(e.as_ref()) => {
+                warn!("fetching token failed (attempt {}): {}", attempt, e);
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Whether a failed fetch may succeed when tried again: the request didn't
+/// get through, or IAM was overloaded or had a server error. An invalid API
+/// key, for one, is not retried.
+fn is_transient(err: &(dyn std::error::Error + 'static)) -> bool {
+    match err.downcast_ref::<IamError>() {
+        Some(e) => e.status.is_server_error() || e.status == StatusCode::TOO_MANY_REQUESTS,
+        None => err.is::<reqwest::Error>(),
+    }
+}
+
+/// An error response from IAM to a token request.
+#[derive(Debug)]
+pub struct IamError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for IamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IAM token request failed with {}: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for IamError {}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+/// Fetches IAM tokens with an API key, caching each one until it expires.
+///
+/// Failed fetches are returned as errors, so `cos::Client` can retry transient
+/// ones, and `refresh` discards the cached token after COS rejects it. The
+/// cache is not locked while a token is fetched, so concurrent requests don't
+/// wait on each other's fetch.
+pub struct IamTokenProvider {
+    api_key: String,
+    endpoint: String,
+    client: reqwest::blocking::Client,
+    token: Mutex<Option<Token>>,
+}
+
+impl IamTokenProvider {
+    /// A provider for `api_key`, using the IAM service at `endpoint`.
+    pub fn new(api_key: &str, endpoint: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            client: reqwest::blocking::Client::new(),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// A provider for the API key in `IBMCLOUD_API_KEY`, using the public IAM endpoint.
+    pub fn from_env() -> Result<Self, Error> {
+        let api_key = std::env::var("IBMCLOUD_API_KEY")
+            .map_err(|_| "'IBMCLOUD_API_KEY' not set or invalid")?;
+        Ok(Self::new(&api_key, DEFAULT_IAM_ENDPOINT))
+    }
+
+    /// The cached token, locked only for as long as the guard is held.
+    fn cached(&self) -> MutexGuard<'_, Option<Token>> {
+        self.token.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn request_token(&self) -> Result<Token, Error> {
+        let url = format!("{}/identity/token", self.endpoint);
+        debug!("POST {}", url);
+
+        let response = self
+            .client
+            .post(url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "urn:ibm:params:oauth:grant-type:apikey"),
+                ("apikey", &self.api_key),
+            ])
+            .send()?;
+
+        let status = response.status();
+        let text = response.text()?;
+        if !status.is_success() {
+            return Err(IamError { status, body: text }.into());
+        }
+
+        let tr: TokenResponse = serde_json::from_str(&text)?;
+        Ok(Token {
+            access_token: tr.access_token,
+            token_type: tr.token_type,
+            refresh_token: tr.refresh_token.unwrap_or_default(),
+            expiry: Instant::now() + Duration::from_secs(tr.expires_in.unwrap_or(1200)),
+        })
+    }
+}
+
+impl CredentialProvider for IamTokenProvider {
+    fn token(&self) -> Result<Token, Error> {
+        if let Some(t) = self.cached().as_ref().filter(|t| t.valid()) {
+            return Ok(t.clone());
+        }
+
+        let t = self.request_token()?;
+        *self.cached() = Some(t.clone());
+        Ok(t)
+    }
+
+    fn refresh(&self) -> Result<Token, Error> {
+        *self.cached() = None;
+
+        let t = self.request_token()?;
+        *self.cached() = Some(t.clone());
+        Ok(t)
+    }
+}
+
+impl CredentialProvider for TokenManager {
+    fn token(&self) -> Result<Token, Error> {
+        TokenManager::token(self)
+    }
+}
+
+/// A pre-fetched token, used as is until it expires.
+impl CredentialProvider for Token {
+    fn token(&self) -> Result<Token, Error> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    fn token(access_token: &str) -> Token {
+        Token {
+            access_token: access_token.to_string(),
+            token_type: "Bearer".to_string(),
+            refresh_token: String::new(),
+            expiry: Instant::now() + Duration::from_secs(60),
+        }
+    }
+
+    /// Fails the first `failures` fetches, and hands out a new token on refresh.
+    #[derive(Default)]
+    struct Flaky {
+        failures: AtomicU32,
+        refreshes: AtomicU32,
+    }
+
+    impl CredentialProvider for Flaky {
+        fn token(&self) -> Result<Token, Error> {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(IamError {
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                    body: String::new(),
+                }
+                .into());
+            }
+            Ok(token("old"))
+        }
+
+        fn refresh(&self) -> Result<Token, Error> {
+            self.refreshes.fetch_add(1, Ordering::SeqCst);
+            Ok(token("new"))
+        }
+    }
+
+    #[test]
+    fn test_fetch_token_retries() {
+        let provider = Flaky {
+            failures: AtomicU32::new(2),
+            ..Default::default()
+        };
+        assert_eq!(fetch_token(&provider, false).unwrap().access_token, "old");
+
+        provider.failures.store(TOKEN_ATTEMPTS, Ordering::SeqCst);
+        assert!(fetch_token(&provider, false).is_err());
+    }
+
+    #[test]
+    fn test_fetch_token_does_not_retry_client_errors() {
+        struct BadKey(AtomicU32);
+
+        impl CredentialProvider for BadKey {
+            fn token(&self) -> Result<Token, Error> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Err(IamError {
+                    status: StatusCode::BAD_REQUEST,
+                    body: "BXNIM0415E".to_string(),
+                }
+                .into())
+            }
+        }
+
+        let provider = BadKey(AtomicU32::new(0));
+        assert!(fetch_token(&provider, false).is_err());
+        assert_eq!(provider.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_unauthorized_refreshes_token() {
+        let provider = Arc::new(Flaky::default());
+        let transport = Arc::new(crate::transport::mock::MockTransport::default());
+        let client = crate::cos::Client::with_transport(
+            provider.clone(),
+            "s3.example.com",
+            transport.clone(),
+        );
+
+        transport
+            .respond(401, "<Error><Code>AccessDenied</Code></Error>")
+            .respond(200, "");
+        client.delete_object("b", "k").unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].headers["authorization"], "Bearer old");
+        assert_eq!(requests[1].headers["authorization"], "Bearer new");
+        assert_eq!(provider.refreshes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_iam_provider_errors_instead_of_panicking() {
+        // nothing listens on port 1, so every fetch fails
+        let provider = IamTokenProvider::new("key", "http://127.0.0.1:1");
+        assert!(provider.token().is_err());
+
+        *provider.token.lock().unwrap() = Some(token("cached"));
+        assert_eq!(provider.token().unwrap().access_token, "cached");
+
+        assert!(provider.refresh().is_err());
+        assert!(provider.token.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_static_token_provider() {
+        let provider: Arc<dyn CredentialProvider> = Arc::new(token("abc"));
+        assert_eq!(provider.token().unwrap().access_token, "abc");
+
+        let _client = crate::cos::Client::new(provider, "s3.example.com");
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::checksum::content_md5;
+use crate::cos::{check_response_text, Client, Error};
+use crate::transport::SendVia;
+
+/// Most keys COS accepts in one multi-object delete request
+pub const MAX_DELETE_KEYS: usize = 1000;
+
+/// Settings for `delete_objects`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeleteOptions {
+    /// Only report the keys that failed, not each deleted key
+    pub quiet: bool,
+    /// Stop after the first batch with failed keys instead of deleting the rest
+    pub fail_fast: bool,
+    /// Log the keys and report them as deleted without deleting anything.
+    /// Only `delete_objects` has a dry run; `abort_multipart_upload` does not.
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename = "Delete")]
+struct Delete {
+    #[serde(rename = "$unflatten=Quiet")]
+    quiet: bool,
+    #[serde(rename = "Object")]
+    objects: Vec<ObjectIdentifier>,
+}
+
+#[derive(Serialize, Debug)]
+struct ObjectIdentifier {
+    #[serde(rename = "$unflatten=Key")]
+    key: String,
+}
+
+/// The outcome of `delete_objects`, per key.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct DeleteResult {
+    /// Empty when `DeleteOptions::quiet` is set
+    #[serde(rename = "Deleted", default)]
+    pub deleted: Vec<DeletedObject>,
+    #[serde(rename = "Error", default)]
+    pub errors: Vec<DeleteError>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct DeletedObject {
+    #[serde(rename = "$unflatten=Key")]
+    pub key: String,
+}
+
+/// A key COS could not delete, e.g. with code `AccessDenied`.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct DeleteError {
+    #[serde(rename = "$unflatten=Key")]
+    pub key: String,
+    #[serde(rename = "$unflatten=Code")]
+    pub code: String,
+    #[serde(rename = "$unflatten=Message", default)]
+    pub message: Option<String>,
+}
+
+impl Client {
+    /// Delete `keys` in batches of `MAX_DELETE_KEYS`.
+    ///
+    /// Keys COS fails to delete are collected in `DeleteResult::errors`; with
+    /// `fail_fast` no further batches are sent once one has failures. A batch
+    /// request that fails outright is returned as an error.
+    #[instrument(skip_all, fields(bucket = bucket, keys = keys.len()))]
+    pub fn delete_objects<S: AsRef<str>>(
+        &self,
+        bucket: &str,
+        keys: &[S],
+        options: &DeleteOptions,
+    ) -> Result<DeleteResult, Error> {
+        let mut result = DeleteResult::default();
+
+        if options.dry_run {
+            for key in keys {
+                info!(bucket, key = key.as_ref(), "dry run, not deleting");
+                result.deleted.push(DeletedObject {
+                    key: key.as_ref().to_string(),
+                });
+            }
+            return Ok(result);
+        }
+
+        for batch in keys.chunks(MAX_DELETE_KEYS) {
+            let mut r = self.delete_batch(bucket, batch, options.quiet)?;
+            let failed = !r.errors.is_empty();
+
+            result.deleted.append(&mut r.deleted);
+            result.errors.append(&mut r.errors);
+
+            if failed && options.fail_fast {
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn delete_batch<S: AsRef<str>>(
+        &self,
+        bucket: &str,
+        keys: &[S],
+        quiet: bool,
+    ) -> Result<DeleteResult, Error> {
+        let c = &self.client;
+        let url = format!("{}?delete", self.bucket_url(bucket));
+
+        let payload = to_string(&Delete {
+            quiet,
+            objects: keys
+                .iter()
+                .map(|k| ObjectIdentifier {
+                    key: k.as_ref().to_string(),
+                })
+                .collect(),
+        })?;
+
+        let response = c
+            .post(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        let text = check_response_text(response)?;
+        Ok(from_str(&text)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_payload() {
+        let payload = to_string(&Delete {
+            quiet: true,
+            objects: vec![ObjectIdentifier {
+                key: "a&b".to_string(),
+            }],
+        })
+        .unwrap();
+        assert_eq!(
+            payload,
+            "<Delete><Quiet>true</Quiet><Object><Key>a&amp;b</Key></Object></Delete>"
+        );
+    }
+
+    #[test]
+    fn test_delete_objects_batches() {
+        let keys: Vec<String> = (0..2500).map(|i| format!("k{}", i)).collect();
+        let failed = r#"<DeleteResult><Error><Key>k0</Key><Code>AccessDenied</Code><Message>Access Denied</Message></Error></DeleteResult>"#;
+
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(200, failed)
+            .respond(
+                200,
+                "<DeleteResult><Deleted><Key>k1000</Key></Deleted></DeleteResult>",
+            )
+            .respond(200, "<DeleteResult/>");
+
+        let result = client
+            .delete_objects("b", &keys, &DeleteOptions::default())
+            .unwrap();
+        assert_eq!(transport.requests().len(), 3);
+        assert_eq!(result.errors[0].code, "AccessDenied");
+        assert_eq!(result.deleted[0].key, "k1000");
+
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, failed);
+
+        let options = DeleteOptions {
+            quiet: true,
+            fail_fast: true,
+            ..Default::default()
+        };
+        let result = client.delete_objects("b", &keys, &options).unwrap();
+        assert_eq!(result.errors.len(), 1);
+
+        let req = &transport.requests()[0];
+        assert_eq!(transport.requests().len(), 1);
+        assert!(req.url.ends_with("?delete"));
+        assert!(req.headers.contains_key("content-md5"));
+        let body = String::from_utf8(req.body.clone().unwrap()).unwrap();
+        assert!(body.starts_with("<Delete><Quiet>true</Quiet><Object><Key>k0</Key>"));
+    }
+
+    #[test]
+    fn test_delete_objects_dry_run() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let options = DeleteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let result = client.delete_objects("b", &["a", "b"], &options).unwrap();
+        assert_eq!(result.deleted.len(), 2);
+        assert_eq!(result.deleted[1].key, "b");
+        assert!(transport.requests().is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+
+const DOMAIN: &str = "cloud-object-storage.appdomain.cloud";
+
+/// Regional, cross-region and single-site locations, which parse as public
+/// endpoints without a `public:` prefix.
+const REGIONS: &[&str] = &[
+    "us-south", "us-east", "eu-gb", "eu-de", "eu-es", "au-syd", "jp-tok", "jp-osa", "ca-tor",
+    "br-sao", "us", "eu", "ap", "ams03", "che01", "mil01", "mon01", "par01", "sjc04", "sng01",
+];
+
+/// A COS endpoint, either derived from a region name or given as a raw hostname.
+///
+/// Parses from strings of the form `us-south`, `private:eu-de`, `direct:jp-tok`,
+/// or a hostname such as `s3.us-south.cloud-object-storage.appdomain.cloud`.
+/// Anything that is not a known region, e.g. `localhost` or `minio:9000`, is
+/// taken as a hostname; use `public:` for a region missing from the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Public(String),
+    Private(String),
+    Direct(String),
+    Custom(String),
+}
+
+impl Endpoint {
+    pub fn public(region: &str) -> Self {
+        Endpoint::Public(region.to_string())
+    }
+
+    pub fn private(region: &str) -> Self {
+        Endpoint::Private(region.to_string())
+    }
+
+    pub fn direct(region: &str) -> Self {
+        Endpoint::Direct(region.to_string())
+    }
+
+    pub fn custom(host: &str) -> Self {
+        Endpoint::Custom(host.to_string())
+    }
+
+    /// The hostname to pass to `Client::new`.
+    pub fn host(&self) -> String {
+        match self {
+            Endpoint::Public(region) => format!("s3.{}.{}", region, DOMAIN),
+            Endpoint::Private(region) => format!("s3.private.{}.{}", region, DOMAIN),
+            Endpoint::Direct(region) => format!("s3.direct.{}.{}", region, DOMAIN),
+            Endpoint::Custom(host) => host.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.host())
+    }
+}
+
+/// Where the bucket name goes in request URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingStyle {
+    /// `https://{bucket}.{endpoint}/{key}`, the default for COS
+    VirtualHosted,
+    /// `https://{endpoint}/{bucket}/{key}`, common for S3-compatible gateways
+    Path,
+}
+
+impl AddressingStyle {
+    /// The `host[:port]` that requests for `bucket` are sent to.
+    pub fn host(&self, endpoint: &str, bucket: &str) -> String {
+        match self {
+            AddressingStyle::VirtualHosted => format!("{}.{}", bucket, endpoint),
+            AddressingStyle::Path => endpoint.to_string(),
+        }
+    }
+
+    /// The path of `key` in `bucket`; an empty key gives the bucket's root path.
+    pub fn path(&self, bucket: &str, key: &str) -> String {
+        match self {
+            AddressingStyle::VirtualHosted => format!("/{}", key),
+            AddressingStyle::Path => format!("/{}/{}", bucket, key),
+        }
+    }
+
+    /// The URL of `bucket`, ending in `/` so keys and queries can be appended.
+    pub fn bucket_url(&self, scheme: &str, endpoint: &str, bucket: &str) -> String {
+        format!(
+            "{}://{}{}",
+            scheme,
+            self.host(endpoint, bucket),
+            self.path(bucket, "")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEndpointError(String);
+
+impl fmt::Display for ParseEndpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid endpoint '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseEndpointError {}
+
+impl FromStr for Endpoint {
+    type Err = ParseEndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(ParseEndpointError(s.to_string()));
+        }
+
+        if let Some((kind, region)) = s.split_once(':') {
+            let ep = match kind {
+                "public" => Endpoint::public(region),
+                "private" => Endpoint::private(region),
+                "direct" => Endpoint::direct(region),
+                // host:port
+                _ => return Ok(Endpoint::custom(s)),
+            };
+
+            if region.is_empty() || region.contains('.') {
+                return Err(ParseEndpointError(s.to_string()));
+            }
+
+            return Ok(ep);
+        }
+
+        if REGIONS.contains(&s) {
+            return Ok(Endpoint::public(s));
+        }
+
+        Ok(Endpoint::custom(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_hosts() {
+        assert_eq!(
+            Endpoint::public("us-south").host(),
+            "s3.us-south.cloud-object-storage.appdomain.cloud"
+        );
+        assert_eq!(
+            Endpoint::private("eu-de").host(),
+            "s3.private.eu-de.cloud-object-storage.appdomain.cloud"
+        );
+        assert_eq!(
+            Endpoint::direct("jp-tok").host(),
+            "s3.direct.jp-tok.cloud-object-storage.appdomain.cloud"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_from_str() {
+        assert_eq!("us-south".parse(), Ok(Endpoint::public("us-south")));
+        assert_eq!("private:eu-de".parse(), Ok(Endpoint::private("eu-de")));
+        assert_eq!("direct:jp-tok".parse(), Ok(Endpoint::direct("jp-tok")));
+        assert_eq!(
+            "s3.us-south.cloud-object-storage.appdomain.cloud".parse(),
+            Ok(Endpoint::custom(
+                "s3.us-south.cloud-object-storage.appdomain.cloud"
+            ))
+        );
+        assert_eq!(
+            "localhost:9000".parse(),
+            Ok(Endpoint::custom("localhost:9000"))
+        );
+        assert_eq!("minio".parse(), Ok(Endpoint::custom("minio")));
+        assert_eq!("public:xx-new".parse(), Ok(Endpoint::public("xx-new")));
+        assert!("private:".parse::<Endpoint>().is_err());
+        assert!("".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn test_addressing_style_urls() {
+        let vh = AddressingStyle::VirtualHosted;
+        assert_eq!(vh.host("s3.example.com", "b"), "b.s3.example.com");
+        assert_eq!(vh.path("b", "k/x"), "/k/x");
+        assert_eq!(
+            vh.bucket_url("https", "s3.example.com", "b"),
+            "https://b.s3.example.com/"
+        );
+
+        let path = AddressingStyle::Path;
+        assert_eq!(path.host("localhost:9000", "b"), "localhost:9000");
+        assert_eq!(path.path("b", "k/x"), "/b/k/x");
+        assert_eq!(
+            path.bucket_url("http", "localhost:9000", "b"),
+            "http://localhost:9000/b/"
+        );
+    }
+}
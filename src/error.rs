@@ -0,0 +1,397 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use quick_xml::de::from_str;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::cos::{Error, RequestContext};
+
+/// Typed errors returned (boxed) by client operations.
+///
+/// Operations return `cos::Error`; use `downcast_ref::<CosError>()` to inspect
+/// the failure.
+#[derive(Debug)]
+pub enum CosError {
+    /// An error response returned by COS that has no more specific variant
+    Api(Box<ApiError>),
+    BucketAlreadyExists(String),
+    BucketAlreadyOwnedByYou(String),
+    /// The bucket still contains objects and must be emptied before deletion
+    BucketNotEmpty(String),
+    /// A request was rejected locally before being sent
+    Validation(String),
+    /// A single PUT of this many bytes exceeds `MAX_PUT_SIZE`; use a multipart upload
+    ObjectTooLarge(u64),
+    /// A response body is larger than the given limit, in bytes
+    BodyTooLarge(u64),
+    /// A checksum computed over the data doesn't match the one COS reported
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// Parts to complete a multipart upload are not numbered 1, 2, 3, ...;
+    /// `found` is `None` when there are no parts at all
+    InvalidPartSequence {
+        expected: usize,
+        found: Option<usize>,
+    },
+    /// A delete was refused because the object is under retention or a legal hold
+    ObjectLocked {
+        bucket: String,
+        key: String,
+        retain_until: Option<DateTime<Utc>>,
+        legal_hold: bool,
+    },
+    /// The object is unchanged according to `If-None-Match` or `If-Modified-Since` (304)
+    NotModified,
+    /// An `If-Match` or `If-None-Match` precondition did not hold (412)
+    PreconditionFailed(Box<ApiError>),
+    /// `move_object` copied the object but could not delete the source, which
+    /// now exists in both places
+    SourceNotDeleted {
+        bucket: String,
+        key: String,
+        source: SendError,
+    },
+    /// The request could not be sent or its response not read
+    Http(reqwest::Error),
+    /// A request or response body was not valid XML for its type
+    Xml(quick_xml::DeError),
+    Io(std::io::Error),
+    Fmt(fmt::Error),
+}
+
+impl fmt::Display for CosError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CosError::Api(e) => write!(f, "{}", e),
+            CosError::BucketAlreadyExists(b) => {
+                write!(f, "bucket '{}' already exists", b)
+            }
+            CosError::BucketAlreadyOwnedByYou(b) => {
+                write!(f, "bucket '{}' already exists and is owned by you", b)
+            }
+            CosError::BucketNotEmpty(b) => {
+                write!(f, "bucket '{}' is not empty, delete its objects first", b)
+            }
+            CosError::Validation(msg) => write!(f, "invalid request: {}", msg),
+            CosError::ObjectTooLarge(size) => write!(
+                f,
+                "{} bytes exceeds the single PUT limit of {} bytes, use a multipart upload",
+                size,
+                crate::object::MAX_PUT_SIZE
+            ),
+            CosError::BodyTooLarge(limit) => {
+                write!(f, "response body exceeds the limit of {} bytes", limit)
+            }
+            CosError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected '{}', got '{}'",
+                expected, actual
+            ),
+            CosError::InvalidPartSequence {
+                expected,
+                found: Some(n),
+            } => write!(f, "expected part number {}, found {}", expected, n),
+            CosError::InvalidPartSequence {
+                expected,
+                found: None,
+            } => write!(f, "expected part number {}, found no more parts", expected),
+            CosError::ObjectLocked {
+                bucket,
+                key,
+                retain_until,
+                legal_hold,
+            } => {
+                write!(f, "object '{}/{}' is locked", bucket, key)?;
+                if let Some(date) = retain_until {
+                    write!(f, " until {}", date.to_rfc3339())?;
+                }
+                if *legal_hold {
+                    write!(f, " under a legal hold")?;
+                }
+                Ok(())
+            }
+            CosError::NotModified => write!(f, "object not modified"),
+            CosError::PreconditionFailed(e) => write!(f, "precondition failed: {}", e),
+            CosError::SourceNotDeleted {
+                bucket,
+                key,
+                source,
+            } => write!(
+                f,
+                "object was copied but deleting the source '{}/{}' failed: {}",
+                bucket, key, source
+            ),
+            CosError::Http(e) => write!(f, "HTTP request failed: {}", e),
+            CosError::Xml(e) => write!(f, "invalid XML: {}", e),
+            CosError::Io(e) => write!(f, "I/O error: {}", e),
+            CosError::Fmt(e) => write!(f, "formatting failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CosError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CosError::SourceNotDeleted { source, .. } => Some(source.as_ref()),
+            CosError::Http(e) => Some(e),
+            CosError::Xml(e) => Some(e),
+            CosError::Io(e) => Some(e),
+            CosError::Fmt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for CosError {
+    fn from(e: reqwest::Error) -> Self {
+        CosError::Http(e)
+    }
+}
+
+impl From<quick_xml::DeError> for CosError {
+    fn from(e: quick_xml::DeError) -> Self {
+        CosError::Xml(e)
+    }
+}
+
+impl From<std::io::Error> for CosError {
+    fn from(e: std::io::Error) -> Self {
+        CosError::Io(e)
+    }
+}
+
+impl From<fmt::Error> for CosError {
+    fn from(e: fmt::Error) -> Self {
+        CosError::Fmt(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    /// The `<Code>` from the error document, e.g. `NoSuchKey`
+    pub code: Option<String>,
+    pub message: Option<String>,
+    pub body: String,
+    /// Identifiers of the failed request, for support tickets
+    pub context: RequestContext,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, body: String) -> Self {
+        let (code, message) = match from_str::<ErrorResponse>(&body) {
+            Ok(e) => (Some(e.code), e.message),
+            Err(_) => (None, None),
+        };
+
+        Self {
+            status,
+            code,
+            message,
+            body,
+            context: RequestContext::default(),
+        }
+    }
+
+    pub(crate) fn from_response(response: reqwest::blocking::Response) -> Self {
+        let status = response.status();
+        let context = RequestContext::from_headers(response.headers());
+
+        // the status is still worth reporting when the body can't be read
+        let body = response
+            .text()
+            .unwrap_or_else(|e| format!("<error reading response body: {}>", e));
+
+        let mut e = Self::new(status, body);
+        e.context = context;
+        e
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "request failed: code='{}' body='{:?}'",
+            self.status, self.body
+        )?;
+
+        if let Some(id) = &self.context.request_id {
+            write!(f, " request_id='{}'", id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    #[serde(rename = "$unflatten=Code")]
+    code: String,
+    #[serde(rename = "$unflatten=Message", default)]
+    message: Option<String>,
+}
+
+/// An error that can be sent between threads, as returned by the concurrent
+/// operations (e.g. `Client::download_objects`).
+pub type SendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Make `err` sendable, keeping a `CosError` intact so it can still be downcast.
+pub(crate) fn into_send_error(err: Error) -> SendError {
+    match err.downcast::<CosError>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
+}
+
+/// Exit code for errors without a more specific code below
+pub const EXIT_FAILURE: u8 = 1;
+/// The bucket or object does not exist (404)
+pub const EXIT_NOT_FOUND: u8 = 3;
+/// The credentials are missing, invalid, or lack permission (401, 403)
+pub const EXIT_ACCESS_DENIED: u8 = 4;
+/// COS asked the client to slow down (429, 503)
+pub const EXIT_THROTTLED: u8 = 5;
+
+/// The process exit code for `err`, as used by the binaries, so scripts can
+/// tell the common failures apart. Invalid arguments exit with 2.
+///
+/// An API error is found even when wrapped, e.g. in the `io::Error` of a
+/// failed read from `get_object_resumable`.
+pub fn exit_code(err: &Error) -> u8 {
+    let e = match find_cos_error(err.as_ref()) {
+        Some(CosError::Api(e)) => e,
+        _ => return EXIT_FAILURE,
+    };
+
+    match (e.status, e.code.as_deref()) {
+        (StatusCode::NOT_FOUND, _) | (_, Some("NoSuchKey" | "NoSuchBucket")) => EXIT_NOT_FOUND,
+        (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, _) | (_, Some("AccessDenied")) => {
+            EXIT_ACCESS_DENIED
+        }
+        (StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE, _)
+        | (_, Some("SlowDown")) => EXIT_THROTTLED,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// The first `CosError` in the chain of `err`.
+fn find_cos_error<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a CosError> {
+    let mut next = Some(err);
+
+    while let Some(e) = next {
+        if let Some(cos) = e.downcast_ref::<CosError>() {
+            return Some(cos);
+        }
+
+        // io::Error::source skips the wrapped error itself
+        next = match e.downcast_ref::<std::io::Error>() {
+            Some(io) => io
+                .get_ref()
+                .map(|inner| inner as &(dyn std::error::Error + 'static)),
+            None => e.source(),
+        };
+    }
+
+    None
+}
+
+/// The COS error code of `err`, if it is an API error response.
+pub(crate) fn api_error_code(err: &Error) -> Option<&str> {
+    match err.downcast_ref::<CosError>() {
+        Some(CosError::Api(e)) => e.code.as_deref(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_parses_code() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Error><Code>BucketAlreadyExists</Code><Message>The requested bucket name is not available.</Message><Resource>/test-bucket/</Resource><RequestId>4b5d6e13-1b1a-4b52-9b57-5e9a0fb2c4c4</RequestId><httpStatusCode>409</httpStatusCode></Error>"#;
+
+        let e = ApiError::new(StatusCode::CONFLICT, body.to_string());
+        assert_eq!(e.code.as_deref(), Some("BucketAlreadyExists"));
+        assert_eq!(
+            e.message.as_deref(),
+            Some("The requested bucket name is not available.")
+        );
+
+        let e = ApiError::new(StatusCode::BAD_GATEWAY, "".to_string());
+        assert_eq!(e.code, None);
+    }
+
+    #[test]
+    fn test_question_mark_converts_to_cos_error() {
+        use std::fmt::Write;
+
+        fn parse(xml: &str) -> Result<ErrorResponse, CosError> {
+            Ok(from_str(xml)?)
+        }
+
+        fn format() -> Result<String, CosError> {
+            let mut s = String::new();
+            write!(s, "{}", 1)?;
+            Ok(s)
+        }
+
+        fn read() -> Result<Vec<u8>, CosError> {
+            Ok(std::fs::read("/nonexistent/cos-test")?)
+        }
+
+        assert!(matches!(parse("<Error><Code>"), Err(CosError::Xml(_))));
+        assert_eq!(format().unwrap(), "1");
+        let err = read().unwrap_err();
+        assert!(matches!(err, CosError::Io(_)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let api = |status, body: &str| -> Error {
+            CosError::Api(Box::new(ApiError::new(status, body.to_string()))).into()
+        };
+
+        assert_eq!(exit_code(&api(StatusCode::NOT_FOUND, "")), EXIT_NOT_FOUND);
+        assert_eq!(
+            exit_code(&api(StatusCode::FORBIDDEN, "")),
+            EXIT_ACCESS_DENIED
+        );
+        assert_eq!(
+            exit_code(&api(
+                StatusCode::BAD_REQUEST,
+                "<Error><Code>SlowDown</Code></Error>"
+            )),
+            EXIT_THROTTLED
+        );
+        assert_eq!(
+            exit_code(&api(StatusCode::INTERNAL_SERVER_ERROR, "")),
+            EXIT_FAILURE
+        );
+        assert_eq!(exit_code(&"other".into()), EXIT_FAILURE);
+
+        let wrapped = std::io::Error::other(into_send_error(api(StatusCode::NOT_FOUND, "")));
+        assert_eq!(exit_code(&wrapped.into()), EXIT_NOT_FOUND);
+    }
+}
@@ -12,6 +12,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod acl;
+pub mod bucket;
+pub mod checksum;
+pub mod cors;
 pub mod cos;
+pub mod credentials;
+pub mod delete;
+pub mod endpoint;
+pub mod error;
 pub mod hmac;
+pub mod lifecycle;
 pub mod multipartupload;
+pub mod object;
+pub mod prelude;
+pub mod progress;
+pub mod restore;
+pub mod resumable;
+pub mod retention;
+pub mod seekable;
+pub mod tagging;
+pub mod transfer;
+pub mod transport;
+pub mod versioning;
+
+pub use cos::{Bucket, Client, Contents, Error};
+pub use error::CosError;
+pub use hmac::Client as HmacClient;
+pub use multipartupload::{CompleteMultipartUpload, Part};
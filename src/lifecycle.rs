@@ -0,0 +1,209 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::checksum::content_md5;
+use crate::cos::{check_response, read_checked, Client, Error};
+use crate::transport::SendVia;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct LifecycleConfiguration {
+    #[serde(rename = "Rule", default)]
+    pub rules: Vec<LifecycleRule>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct LifecycleRule {
+    #[serde(rename = "$unflatten=ID")]
+    pub id: String,
+    #[serde(rename = "Filter", default)]
+    pub filter: LifecycleFilter,
+    /// `Enabled` or `Disabled`
+    #[serde(rename = "$unflatten=Status")]
+    pub status: String,
+    #[serde(
+        rename = "Expiration",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expiration: Option<Expiration>,
+    #[serde(rename = "Transition", default)]
+    pub transitions: Vec<Transition>,
+}
+
+impl LifecycleRule {
+    /// An enabled rule applying to keys starting with `prefix` (all keys if empty).
+    pub fn new(id: &str, prefix: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            filter: LifecycleFilter {
+                prefix: Some(prefix.to_string()),
+            },
+            status: "Enabled".to_string(),
+            expiration: None,
+            transitions: Vec::new(),
+        }
+    }
+
+    pub fn expire_after_days(mut self, days: u32) -> Self {
+        self.expiration = Some(Expiration {
+            days: Some(days),
+            date: None,
+        });
+        self
+    }
+
+    pub fn transition_after_days(mut self, days: u32, storage_class: &str) -> Self {
+        self.transitions.push(Transition {
+            days: Some(days),
+            date: None,
+            storage_class: storage_class.to_string(),
+        });
+        self
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct LifecycleFilter {
+    #[serde(
+        rename = "$unflatten=Prefix",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub prefix: Option<String>,
+}
+
+/// When objects expire, either a number of days after creation or on an ISO 8601 date.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Expiration {
+    #[serde(
+        rename = "$unflatten=Days",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub days: Option<u32>,
+    #[serde(
+        rename = "$unflatten=Date",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub date: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Transition {
+    #[serde(
+        rename = "$unflatten=Days",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub days: Option<u32>,
+    #[serde(
+        rename = "$unflatten=Date",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub date: Option<String>,
+    /// Target storage class, e.g. `GLACIER`
+    #[serde(rename = "$unflatten=StorageClass")]
+    pub storage_class: String,
+}
+
+impl Client {
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_bucket_lifecycle(&self, bucket: &str) -> Result<Vec<LifecycleRule>, Error> {
+        let c = &self.client;
+        let url = format!("{}?lifecycle", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let config: LifecycleConfiguration = from_str(&text)?;
+
+        Ok(config.rules)
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn put_bucket_lifecycle(
+        &self,
+        bucket: &str,
+        rules: Vec<LifecycleRule>,
+    ) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?lifecycle", self.bucket_url(bucket));
+
+        let payload = to_string(&LifecycleConfiguration { rules })?;
+
+        let response = c
+            .put(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn delete_bucket_lifecycle(&self, bucket: &str) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?lifecycle", self.bucket_url(bucket));
+
+        let response = c.delete(url).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_configuration_xml() {
+        let config = LifecycleConfiguration {
+            rules: vec![
+                LifecycleRule::new("expire-logs", "logs/").expire_after_days(30),
+                LifecycleRule::new("archive", "").transition_after_days(90, "GLACIER"),
+            ],
+        };
+
+        let exp = "<LifecycleConfiguration><Rule><ID>expire-logs</ID><Filter><Prefix>logs/</Prefix></Filter><Status>Enabled</Status><Expiration><Days>30</Days></Expiration></Rule><Rule><ID>archive</ID><Filter><Prefix></Prefix></Filter><Status>Enabled</Status><Transition><Days>90</Days><StorageClass>GLACIER</StorageClass></Transition></Rule></LifecycleConfiguration>";
+        assert_eq!(to_string(&config).unwrap(), exp);
+    }
+
+    #[test]
+    fn test_lifecycle_configuration_parse() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><LifecycleConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Rule><ID>expire-logs</ID><Filter><Prefix>logs/</Prefix></Filter><Status>Enabled</Status><Expiration><Date>2024-01-01T00:00:00.000Z</Date></Expiration></Rule></LifecycleConfiguration>"#;
+
+        let config: LifecycleConfiguration = from_str(input).unwrap();
+        assert_eq!(config.rules.len(), 1);
+
+        let rule = &config.rules[0];
+        assert_eq!(rule.id, "expire-logs");
+        assert_eq!(rule.filter.prefix.as_deref(), Some("logs/"));
+        assert_eq!(
+            rule.expiration,
+            Some(Expiration {
+                days: None,
+                date: Some("2024-01-01T00:00:00.000Z".to_string()),
+            })
+        );
+        assert!(rule.transitions.is_empty());
+    }
+}
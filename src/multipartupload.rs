@@ -12,11 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
 use quick_xml::{de::from_str, se::to_string};
 use reqwest::blocking::Body;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use tracing::instrument;
+
+use crate::checksum::{verify_echoed, ChecksumAlgorithm};
+use crate::cos::{check_response, check_response_text, read_checked, Client, Error};
+use crate::error::{into_send_error, CosError, SendError};
+use crate::object::{deserialize_etag, unquote_etag, PutObjectOptions};
+use crate::transport::SendVia;
 
-use crate::cos::{check_response, Client, Error};
+/// Smallest part size COS accepts for any part but the last
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Largest number of parts in a single upload
+pub const MAX_PARTS: usize = 10_000;
 
 #[derive(Deserialize, Debug)]
 pub struct InitiateMultipartUploadResult {
@@ -28,12 +43,48 @@ pub struct InitiateMultipartUploadResult {
     pub upload_id: String,
 }
 
+/// An uploaded part, as returned by `upload_part`; build one with `Part::new`.
 #[derive(Deserialize, Serialize, Debug, Clone)]
+#[non_exhaustive]
 pub struct Part {
-    #[serde(rename = "$unflatten=ETag")]
+    /// Unquoted ETag of the part; quoted again when completing the upload
+    #[serde(
+        rename = "$unflatten=ETag",
+        deserialize_with = "deserialize_etag",
+        serialize_with = "serialize_etag"
+    )]
     pub etag: String,
     #[serde(rename = "$unflatten=PartNumber")]
     pub part_number: usize,
+    /// Size of the uploaded part, when known; not sent to COS
+    #[serde(skip)]
+    pub size: Option<u64>,
+    /// Set by `upload_part_with_checksum`, and sent back when completing
+    #[serde(
+        rename = "$unflatten=ChecksumSHA256",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub checksum_sha256: Option<String>,
+    #[serde(
+        rename = "$unflatten=ChecksumCRC32C",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub checksum_crc32c: Option<String>,
+}
+
+impl Part {
+    /// A part with an unquoted `etag`, of unknown size and without checksums.
+    pub fn new(part_number: usize, etag: &str) -> Self {
+        Part {
+            etag: etag.to_string(),
+            part_number,
+            size: None,
+            checksum_sha256: None,
+            checksum_crc32c: None,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -42,22 +93,72 @@ pub struct CompleteMultipartUpload {
     pub parts: Vec<Part>,
 }
 
+/// The object created by completing a multipart upload.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CompletedUpload {
+    #[serde(rename = "$unflatten=Location", default)]
+    pub location: Option<String>,
+    #[serde(rename = "$unflatten=Bucket")]
+    pub bucket: String,
+    #[serde(rename = "$unflatten=Key")]
+    pub key: String,
+    /// Unquoted multipart ETag, e.g. `3858f62230ac3c915f300c664312c11f-9`
+    #[serde(rename = "$unflatten=ETag", deserialize_with = "deserialize_etag")]
+    pub etag: String,
+}
+
+impl CompleteMultipartUpload {
+    /// Sort the parts by number, checking they run 1, 2, 3, ... without gaps
+    /// or duplicates.
+    pub fn sort_parts(&mut self) -> Result<(), CosError> {
+        self.parts.sort_by_key(|p| p.part_number);
+
+        if self.parts.is_empty() {
+            return Err(CosError::InvalidPartSequence {
+                expected: 1,
+                found: None,
+            });
+        }
+
+        for (i, part) in self.parts.iter().enumerate() {
+            if part.part_number != i + 1 {
+                return Err(CosError::InvalidPartSequence {
+                    expected: i + 1,
+                    found: Some(part.part_number),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn serialize_etag<S: Serializer>(etag: &str, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("\"{}\"", etag))
+}
+
 pub type UploadId = String;
 
 impl Client {
     pub fn create_multipart_upload(&self, bucket: &str, key: &str) -> Result<UploadId, Error> {
+        self.create_multipart_upload_with_options(bucket, key, &PutObjectOptions::default())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn create_multipart_upload_with_options(
+        &self,
+        bucket: &str,
+        key: &str,
+        options: &PutObjectOptions,
+    ) -> Result<UploadId, Error> {
+        let headers = options.headers()?;
+
         let c = &self.client;
 
-        let url = format!("https://{}.{}/{}?uploads", bucket, self.endpoint, key);
-        let response = c
-            .post(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .send()?;
+        let url = format!("{}{}?uploads", self.bucket_url(bucket), key);
+        let response = c.post(url).headers(headers).send_via(self)?;
 
-        let text: String = check_response(response)?.text()?;
+        let text = read_checked(response)?;
         let mpu_resp: InitiateMultipartUploadResult = from_str(&text)?;
 
         Ok(mpu_resp.upload_id)
@@ -70,64 +171,95 @@ impl Client {
         upload_id: &str,
         sequence_number: usize,
         chunk: T,
+    ) -> Result<Part, Error> {
+        self.upload_part_with_checksum(bucket, key, upload_id, sequence_number, chunk, None)
+    }
+
+    /// Like `upload_part`, sending a checksum of the chunk for COS to verify.
+    /// The upload should be created with the same `PutObjectOptions::checksum`.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn upload_part_with_checksum<T: Into<Body>>(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        sequence_number: usize,
+        chunk: T,
+        checksum: Option<ChecksumAlgorithm>,
     ) -> Result<Part, Error> {
         let c = &self.client;
 
         let url = format!(
-            "https://{}.{}/{}?partNumber={}&uploadId={}",
-            bucket, self.endpoint, key, sequence_number, upload_id,
+            "{}{}?partNumber={}&uploadId={}",
+            self.bucket_url(bucket),
+            key,
+            sequence_number,
+            upload_id,
         );
 
-        let resp = c
-            .put(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .body(chunk)
-            .send()?;
+        let chunk: Body = chunk.into();
+        let size = chunk.as_bytes().map(|b| b.len() as u64);
+
+        let checksum = match (checksum, chunk.as_bytes()) {
+            (Some(alg), Some(b)) => Some((alg, alg.compute(b))),
+            (Some(_), None) => {
+                return Err(CosError::Validation(
+                    "a checksum requires the part to be in memory".to_string(),
+                )
+                .into())
+            }
+            (None, _) => None,
+        };
+
+        let mut req = c.put(url);
+        if let Some(t) = self.upload_timeout {
+            req = req.timeout(t);
+        }
+        if let Some((alg, value)) = &checksum {
+            req = req.header(alg.header_name(), value.as_str());
+        }
 
-        let resp = check_response(resp)?;
+        let resp = check_response(req.body(chunk).send_via(self)?)?;
         let etag = resp.headers()[reqwest::header::ETAG].to_str().unwrap();
 
-        let part = Part {
-            etag: etag.to_string(),
-            part_number: sequence_number,
-        };
+        let mut part = Part::new(sequence_number, unquote_etag(etag));
+        part.size = size;
+
+        if let Some((alg, value)) = checksum {
+            verify_echoed(resp.headers(), alg, &value)?;
+            match alg {
+                ChecksumAlgorithm::Sha256 => part.checksum_sha256 = Some(value),
+                ChecksumAlgorithm::Crc32c => part.checksum_crc32c = Some(value),
+            }
+        }
 
         Ok(part)
     }
 
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
     pub fn complete_multipart_upload(
         &self,
         bucket: &str,
         key: &str,
         upload_id: &str,
-        cmpu: CompleteMultipartUpload,
-    ) -> Result<(), Error> {
+        mut cmpu: CompleteMultipartUpload,
+    ) -> Result<CompletedUpload, Error> {
+        cmpu.sort_parts()?;
+
         let c = &self.client;
 
-        let url = format!(
-            "https://{}.{}/{}?uploadId={}",
-            bucket, self.endpoint, key, upload_id
-        );
+        let url = format!("{}{}?uploadId={}", self.bucket_url(bucket), key, upload_id);
 
         let payload = to_string(&cmpu).unwrap();
 
-        let resp = c
-            .post(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .body(payload)
-            .send()?;
+        let resp = c.post(url).body(payload).send_via(self)?;
 
-        let _ = check_response(resp)?;
-
-        Ok(())
+        // COS may report a failure as a 200 with an error document
+        let text = check_response_text(resp)?;
+        Ok(from_str(&text)?)
     }
 
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
     pub fn abort_multipart_upload(
         &self,
         bucket: &str,
@@ -136,21 +268,342 @@ impl Client {
     ) -> Result<(), Error> {
         let c = &self.client;
 
-        let url = format!(
-            "https://{}.{}/{}?uploadId={}",
-            bucket, self.endpoint, key, upload_id
-        );
+        let url = format!("{}{}?uploadId={}", self.bucket_url(bucket), key, upload_id);
 
-        let resp = c
-            .delete(url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.tm.token()?.access_token),
-            )
-            .send()?;
+        let resp = c.delete(url).send_via(self)?;
 
         let _ = check_response(resp)?;
 
         Ok(())
     }
+
+    /// Upload everything read from `r` as a multipart upload of `part_size` chunks.
+    ///
+    /// The upload is aborted if any part or the completion fails.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn upload_multipart<R: Read>(
+        &self,
+        bucket: &str,
+        key: &str,
+        r: R,
+        part_size: usize,
+        options: &PutObjectOptions,
+    ) -> Result<(), Error> {
+        self.upload_multipart_with(bucket, key, part_size, options, |upload_id| {
+            let mut chunks = Chunks::new(r, part_size);
+            let mut parts = Vec::new();
+
+            while let Some((seq_no, chunk)) = chunks.next_chunk()? {
+                parts.push(self.upload_part_with_checksum(
+                    bucket,
+                    key,
+                    upload_id,
+                    seq_no,
+                    chunk,
+                    options.checksum,
+                )?);
+            }
+
+            Ok(parts)
+        })
+    }
+
+    /// Like `upload_multipart`, uploading up to `concurrency` parts at once, so
+    /// up to `concurrency` parts are held in memory.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn upload_multipart_concurrent<R: Read + Send>(
+        &self,
+        bucket: &str,
+        key: &str,
+        r: R,
+        part_size: usize,
+        concurrency: usize,
+        options: &PutObjectOptions,
+    ) -> Result<(), Error> {
+        self.upload_multipart_with(bucket, key, part_size, options, |upload_id| {
+            let chunks = Mutex::new(Chunks::new(r, part_size));
+            // set by the first failing worker so the others stop taking parts
+            let failed = AtomicBool::new(false);
+
+            let upload = || -> Result<Vec<Part>, Error> {
+                let mut parts = Vec::new();
+
+                while !failed.load(Ordering::Relaxed) {
+                    let next = chunks.lock().unwrap().next_chunk()?;
+                    let Some((seq_no, chunk)) = next else {
+                        break;
+                    };
+
+                    parts.push(self.upload_part_with_checksum(
+                        bucket,
+                        key,
+                        upload_id,
+                        seq_no,
+                        chunk,
+                        options.checksum,
+                    )?);
+                }
+
+                Ok(parts)
+            };
+
+            let results: Vec<Result<Vec<Part>, SendError>> = thread::scope(|s| {
+                let workers: Vec<_> = (0..concurrency.max(1))
+                    .map(|_| {
+                        s.spawn(|| {
+                            upload().map_err(|e| {
+                                failed.store(true, Ordering::Relaxed);
+                                into_send_error(e)
+                            })
+                        })
+                    })
+                    .collect();
+
+                workers
+                    .into_iter()
+                    .map(|w| w.join().expect("upload worker panicked"))
+                    .collect()
+            });
+
+            // completing the upload sorts the parts by number
+            let mut parts = Vec::new();
+            for res in results {
+                parts.extend(res.map_err(|e| e as Error)?);
+            }
+
+            Ok(parts)
+        })
+    }
+
+    /// Create an upload, add its parts with `upload_parts` and complete it,
+    /// aborting it if anything fails.
+    fn upload_multipart_with<F>(
+        &self,
+        bucket: &str,
+        key: &str,
+        part_size: usize,
+        options: &PutObjectOptions,
+        upload_parts: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&str) -> Result<Vec<Part>, Error>,
+    {
+        if part_size < MIN_PART_SIZE {
+            return Err(CosError::Validation(format!(
+                "part size {} is below the minimum of {} bytes",
+                part_size, MIN_PART_SIZE
+            ))
+            .into());
+        }
+
+        let upload_id = self.create_multipart_upload_with_options(bucket, key, options)?;
+
+        let res = upload_parts(&upload_id).and_then(|parts| {
+            let cmpu = CompleteMultipartUpload { parts };
+            self.complete_multipart_upload(bucket, key, &upload_id, cmpu)
+                .map(|_| ())
+        });
+
+        if res.is_err() {
+            let _ = self.abort_multipart_upload(bucket, key, &upload_id);
+        }
+
+        res
+    }
+}
+
+/// Splits a reader into numbered parts of `part_size` bytes.
+struct Chunks<R> {
+    r: R,
+    part_size: usize,
+    next: usize,
+    done: bool,
+}
+
+impl<R: Read> Chunks<R> {
+    fn new(r: R, part_size: usize) -> Self {
+        Chunks {
+            r,
+            part_size,
+            next: 1,
+            done: false,
+        }
+    }
+
+    /// The next part number and its data, or `None` after the last part.
+    fn next_chunk(&mut self) -> Result<Option<(usize, Vec<u8>)>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let mut chunk = Vec::with_capacity(self.part_size);
+        let n = self
+            .r
+            .by_ref()
+            .take(self.part_size as u64)
+            .read_to_end(&mut chunk)?;
+
+        // an empty object still needs one (empty) part
+        if n == 0 && self.next > 1 {
+            self.done = true;
+            return Ok(None);
+        }
+
+        if self.next > MAX_PARTS {
+            return Err(CosError::Validation(format!(
+                "upload exceeds {} parts of {} bytes",
+                MAX_PARTS, self.part_size
+            ))
+            .into());
+        }
+
+        self.done = n < self.part_size;
+        self.next += 1;
+
+        Ok(Some((self.next - 1, chunk)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts() -> CompleteMultipartUpload {
+        CompleteMultipartUpload {
+            parts: vec![Part::new(1, "abc")],
+        }
+    }
+
+    #[test]
+    fn test_complete_multipart_upload_result() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><CompleteMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Location>https://bucket.s3.example.com/key</Location><Bucket>bucket</Bucket><Key>key</Key><ETag>"3858f62230ac3c915f300c664312c11f-2"</ETag></CompleteMultipartUploadResult>"#);
+
+        let done = client
+            .complete_multipart_upload("bucket", "key", "id", parts())
+            .unwrap();
+        assert_eq!(done.key, "key");
+        assert_eq!(done.etag, "3858f62230ac3c915f300c664312c11f-2");
+        assert_eq!(
+            done.location.as_deref(),
+            Some("https://bucket.s3.example.com/key")
+        );
+    }
+
+    #[test]
+    fn test_complete_multipart_upload_error_with_200() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(
+            200,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>InternalError</Code><Message>We encountered an internal error.</Message></Error>"#,
+        );
+
+        let err = client
+            .complete_multipart_upload("bucket", "key", "id", parts())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::Api(e)) if e.code.as_deref() == Some("InternalError")
+        ));
+    }
+
+    #[test]
+    fn test_upload_part_with_checksum() {
+        let (client, transport) = crate::transport::mock::client();
+        let sum = ChecksumAlgorithm::Sha256.compute(b"chunk");
+        transport.respond_with_headers(
+            200,
+            vec![
+                ("etag", "\"abc\"".to_string()),
+                ("x-amz-checksum-sha256", sum.clone()),
+            ],
+            "",
+        );
+
+        let part = client
+            .upload_part_with_checksum("b", "k", "id", 1, "chunk", Some(ChecksumAlgorithm::Sha256))
+            .unwrap();
+        assert_eq!(part.checksum_sha256.as_deref(), Some(sum.as_str()));
+        assert_eq!(
+            transport.requests()[0].headers["x-amz-checksum-sha256"],
+            sum.as_str()
+        );
+
+        let xml = to_string(&CompleteMultipartUpload { parts: vec![part] }).unwrap();
+        assert!(xml.contains(&format!("<ChecksumSHA256>{}</ChecksumSHA256>", sum)));
+        assert!(!to_string(&parts()).unwrap().contains("Checksum"));
+    }
+
+    #[test]
+    fn test_complete_sorts_parts() {
+        let part = |n: usize| Part::new(n, &format!("e{}", n));
+
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, "<CompleteMultipartUploadResult><Bucket>b</Bucket><Key>k</Key><ETag>\"abc-3\"</ETag></CompleteMultipartUploadResult>");
+
+        let cmpu = CompleteMultipartUpload {
+            parts: vec![part(3), part(1), part(2)],
+        };
+        client
+            .complete_multipart_upload("b", "k", "id", cmpu)
+            .unwrap();
+
+        let body = String::from_utf8(transport.requests()[0].body.clone().unwrap()).unwrap();
+        let pos = |n: &str| {
+            body.find(&format!("<PartNumber>{}</PartNumber>", n))
+                .unwrap()
+        };
+        assert!(pos("1") < pos("2") && pos("2") < pos("3"));
+
+        for parts in [
+            vec![part(1), part(3)],
+            vec![part(2), part(1), part(2)],
+            vec![],
+        ] {
+            let err = client
+                .complete_multipart_upload("b", "k", "id", CompleteMultipartUpload { parts })
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CosError>(),
+                Some(CosError::InvalidPartSequence { .. })
+            ));
+        }
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_upload_multipart_concurrent() {
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(200, "<InitiateMultipartUploadResult><Bucket>b</Bucket><Key>k</Key><UploadId>id</UploadId></InitiateMultipartUploadResult>");
+        for _ in 0..3 {
+            transport.respond_with_headers(200, vec![("etag", "\"e\"".to_string())], "");
+        }
+        transport.respond(200, "<CompleteMultipartUploadResult><Bucket>b</Bucket><Key>k</Key><ETag>\"abc-3\"</ETag></CompleteMultipartUploadResult>");
+
+        let data = vec![0u8; 2 * MIN_PART_SIZE + 1];
+        client
+            .upload_multipart_concurrent(
+                "b",
+                "k",
+                &data[..],
+                MIN_PART_SIZE,
+                2,
+                &PutObjectOptions::default(),
+            )
+            .unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 5);
+        let mut sizes: Vec<_> = requests[1..4]
+            .iter()
+            .map(|r| r.body.as_ref().unwrap().len())
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![1, MIN_PART_SIZE, MIN_PART_SIZE]);
+
+        let body = String::from_utf8(requests[4].body.clone().unwrap()).unwrap();
+        assert_eq!(body.matches("<PartNumber>").count(), 3);
+    }
 }
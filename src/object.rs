@@ -0,0 +1,1457 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use md5::{Digest, Md5};
+use quick_xml::de::from_str;
+use reqwest::blocking::Body;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_ENCODING,
+    CONTENT_TYPE, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Deserializer};
+use tracing::instrument;
+use urlencoding::encode;
+
+use crate::acl::CannedAcl;
+use crate::checksum::{checksum_from_headers, verify_echoed, ChecksumAlgorithm, ChecksumReader};
+use crate::cos::{
+    check_response, check_response_text, parse_timestamp, Client, Error, RequestContext,
+};
+use crate::error::{into_send_error, CosError};
+use crate::restore::RestoreStatus;
+use crate::retention::{format_retain_until, ObjectRetention, RetentionMode};
+use crate::transport::SendVia;
+use crate::versioning::object_url;
+
+const META_PREFIX: &str = "x-amz-meta-";
+
+/// Largest object a single PUT can upload; use a multipart upload above this.
+///
+/// Bodies of known length over the limit fail up front with
+/// `CosError::ObjectTooLarge`. Streamed bodies of unknown length cannot be
+/// checked, and COS rejects them only once the limit is passed.
+pub const MAX_PUT_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Fail a PUT of `size` bytes over [`MAX_PUT_SIZE`] before sending it.
+pub(crate) fn check_put_size(size: u64) -> Result<(), CosError> {
+    if size > MAX_PUT_SIZE {
+        return Err(CosError::ObjectTooLarge(size));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+    Standard,
+    Vault,
+    ColdVault,
+    Flex,
+    Smart,
+    /// Archived objects, which must be restored before they can be read
+    Glacier,
+}
+
+impl StorageClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::Vault => "VAULT",
+            StorageClass::ColdVault => "COLD",
+            StorageClass::Flex => "FLEX",
+            StorageClass::Smart => "SMART",
+            StorageClass::Glacier => "GLACIER",
+        }
+    }
+}
+
+impl fmt::Display for StorageClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for StorageClass {
+    type Err = CosError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "STANDARD" => Ok(StorageClass::Standard),
+            "VAULT" => Ok(StorageClass::Vault),
+            "COLD" | "COLD_VAULT" | "COLDVAULT" => Ok(StorageClass::ColdVault),
+            "FLEX" => Ok(StorageClass::Flex),
+            "SMART" => Ok(StorageClass::Smart),
+            "GLACIER" => Ok(StorageClass::Glacier),
+            _ => Err(CosError::Validation(format!(
+                "unknown storage class '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Guess a Content-Type from the extension of an object key or file name.
+pub fn content_type_from_extension(key: &str) -> Option<&'static str> {
+    let ext = key.rsplit_once('.')?.1.to_ascii_lowercase();
+
+    let ct = match ext.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "wasm" => "application/wasm",
+        "mp4" => "video/mp4",
+        "mp3" => "audio/mpeg",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => return None,
+    };
+
+    Some(ct)
+}
+
+/// Object attributes returned by `head_object` and `get_object_with_metadata`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjectMetadata {
+    pub content_length: u64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub storage_class: Option<String>,
+    /// Set in versioned buckets
+    pub version_id: Option<String>,
+    /// The raw `x-amz-restore` header of archived objects
+    pub restore: Option<String>,
+    /// User metadata from `x-amz-meta-*` headers, keyed without the prefix
+    pub metadata: HashMap<String, String>,
+    /// The Key Protect or HPCS root key the object is encrypted with
+    pub kp_root_key_crn: Option<String>,
+    /// The object can't be deleted or overwritten until this date, from object
+    /// lock or the bucket's retention policy
+    pub retain_until: Option<DateTime<Utc>>,
+    /// The object is under at least one legal hold
+    pub legal_hold: bool,
+    pub context: RequestContext,
+}
+
+impl ObjectMetadata {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        let mut metadata = HashMap::new();
+        for (name, value) in headers.iter() {
+            if let Some(key) = name.as_str().strip_prefix(META_PREFIX) {
+                if let Ok(v) = value.to_str() {
+                    metadata.insert(key.to_string(), v.to_string());
+                }
+            }
+        }
+
+        Self {
+            content_length: get("content-length")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            content_type: get("content-type"),
+            etag: get("etag").map(|e| unquote_etag(&e).to_string()),
+            last_modified: get("last-modified"),
+            storage_class: get("x-amz-storage-class"),
+            version_id: get("x-amz-version-id"),
+            restore: get("x-amz-restore"),
+            metadata,
+            kp_root_key_crn: get(KP_ROOT_KEY_CRN),
+            // object lock sends RFC 3339 dates, bucket retention policies HTTP dates
+            retain_until: get("x-amz-object-lock-retain-until-date")
+                .or_else(|| get("retention-expiration-date"))
+                .and_then(|d| {
+                    parse_timestamp(&d)
+                        .or_else(|_| {
+                            DateTime::parse_from_rfc2822(&d).map(|d| d.with_timezone(&Utc))
+                        })
+                        .ok()
+                }),
+            legal_hold: get("x-amz-object-lock-legal-hold").as_deref() == Some("ON")
+                || get("retention-legal-hold-count")
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .is_some_and(|n| n > 0),
+            context: RequestContext::from_headers(headers),
+        }
+    }
+
+    /// Progress of a restore of an archived object, if one was requested.
+    pub fn restore_status(&self) -> Option<RestoreStatus> {
+        self.restore.as_deref().and_then(RestoreStatus::parse)
+    }
+}
+
+/// Strip the double quotes COS wraps ETag values in, e.g. `"d41d8..."` becomes `d41d8...`.
+///
+/// ETags returned by this crate are always unquoted.
+pub fn unquote_etag(etag: &str) -> &str {
+    etag.strip_prefix('"')
+        .and_then(|e| e.strip_suffix('"'))
+        .unwrap_or(etag)
+}
+
+pub(crate) fn deserialize_etag<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    let etag = String::deserialize(d)?;
+    Ok(unquote_etag(&etag).to_string())
+}
+
+/// The outcome of `Client::verify_object`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// The object's MD5 matches its ETag
+    Match,
+    Mismatch,
+    /// The ETag isn't the object's MD5, e.g. for multipart uploads (`{hash}-{parts}`)
+    NotSimpleMd5,
+}
+
+pub(crate) fn is_md5_etag(etag: &str) -> bool {
+    etag.len() == 32 && etag.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+const KP_ALGORITHM: &str = "ibm-sse-kp-encryption-algorithm";
+const KP_ROOT_KEY_CRN: &str = "ibm-sse-kp-customer-root-key-crn";
+
+/// Encryption with a root key managed in IBM Key Protect or Hyper Protect
+/// Crypto Services.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyProtect {
+    /// `AES256`, the only algorithm COS supports
+    pub algorithm: String,
+    /// e.g. `crn:v1:bluemix:public:kms:us-south:a/{account}:{instance}:key:{key id}`
+    pub root_key_crn: String,
+}
+
+impl KeyProtect {
+    pub fn new(root_key_crn: &str) -> Self {
+        Self {
+            algorithm: "AES256".to_string(),
+            root_key_crn: root_key_crn.to_string(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), CosError> {
+        // crn:v1:{cname}:{ctype}:{service}:{location}:{scope}:{instance}:key:{key id}
+        let parts: Vec<&str> = self.root_key_crn.split(':').collect();
+
+        let valid = parts.len() == 10
+            && parts[..2] == ["crn", "v1"]
+            && ["kms", "hs-crypto"].contains(&parts[4])
+            && parts[8] == "key"
+            && !parts[7].is_empty()
+            && !parts[9].is_empty();
+
+        if !valid {
+            return Err(CosError::Validation(format!(
+                "invalid root key CRN '{}'",
+                self.root_key_crn
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Optional settings for `put_object_with_options` and
+/// `create_multipart_upload_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct PutObjectOptions {
+    /// User metadata, sent as `x-amz-meta-{key}` headers
+    pub metadata: HashMap<String, String>,
+    /// Storage class for the object, instead of the bucket default
+    pub storage_class: Option<StorageClass>,
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_encoding: Option<String>,
+    pub cache_control: Option<String>,
+    /// Canned ACL for the object, sent as `x-amz-acl`
+    pub acl: Option<CannedAcl>,
+    /// Object lock retention to apply on upload
+    pub retention: Option<ObjectRetention>,
+    /// Place a legal hold on the object on upload
+    pub legal_hold: Option<bool>,
+    /// Only upload if no object matches; `*` refuses to overwrite any existing object
+    pub if_none_match: Option<String>,
+    /// Headers the crate doesn't model, e.g. `x-amz-request-payer`; see `extra_headers`
+    pub extra_headers: HashMap<String, String>,
+    /// Send a checksum of the body for COS to verify; the body must be in memory
+    pub checksum: Option<ChecksumAlgorithm>,
+    /// Encrypt the object with a Key Protect root key
+    pub key_protect: Option<KeyProtect>,
+}
+
+impl PutObjectOptions {
+    pub(crate) fn headers(&self) -> Result<HeaderMap, CosError> {
+        let mut headers = HeaderMap::new();
+
+        if let Some(class) = self.storage_class {
+            headers.insert(
+                "x-amz-storage-class",
+                HeaderValue::from_static(class.as_str()),
+            );
+        }
+
+        if let Some(acl) = self.acl {
+            headers.insert("x-amz-acl", HeaderValue::from_static(acl.as_str()));
+        }
+
+        if let Some(r) = &self.retention {
+            headers.insert(
+                "x-amz-object-lock-mode",
+                HeaderValue::from_static(r.mode.as_str()),
+            );
+            headers.insert(
+                "x-amz-object-lock-retain-until-date",
+                HeaderValue::from_str(&format_retain_until(&r.retain_until))
+                    .expect("RFC 3339 dates are valid header values"),
+            );
+        }
+
+        if let Some(etag) = &self.if_none_match {
+            headers.insert(IF_NONE_MATCH, etag_header(etag)?);
+        }
+
+        if let Some(alg) = self.checksum {
+            headers.insert(
+                "x-amz-checksum-algorithm",
+                HeaderValue::from_static(alg.as_str()),
+            );
+        }
+
+        if let Some(kp) = &self.key_protect {
+            kp.validate()?;
+
+            let value = |v: &str| {
+                HeaderValue::from_str(v)
+                    .map_err(|_| CosError::Validation(format!("invalid header value '{}'", v)))
+            };
+            headers.insert(KP_ALGORITHM, value(&kp.algorithm)?);
+            headers.insert(KP_ROOT_KEY_CRN, value(&kp.root_key_crn)?);
+        }
+
+        if let Some(on) = self.legal_hold {
+            headers.insert(
+                "x-amz-object-lock-legal-hold",
+                HeaderValue::from_static(if on { "ON" } else { "OFF" }),
+            );
+        }
+
+        let content_headers = [
+            (CONTENT_TYPE, &self.content_type),
+            (CONTENT_DISPOSITION, &self.content_disposition),
+            (CONTENT_ENCODING, &self.content_encoding),
+            (CACHE_CONTROL, &self.cache_control),
+        ];
+
+        for (name, value) in content_headers {
+            if let Some(v) = value {
+                let v = HeaderValue::from_str(v)
+                    .map_err(|_| CosError::Validation(format!("invalid {} '{}'", name, v)))?;
+                headers.insert(name, v);
+            }
+        }
+
+        for (key, value) in &self.metadata {
+            let name = HeaderName::from_bytes(format!("{}{}", META_PREFIX, key).as_bytes())
+                .map_err(|_| CosError::Validation(format!("invalid metadata key '{}'", key)))?;
+
+            if value.contains(['\r', '\n']) {
+                return Err(CosError::Validation(format!(
+                    "value for metadata key '{}' contains a newline",
+                    key
+                )));
+            }
+
+            let value = HeaderValue::from_str(value).map_err(|_| {
+                CosError::Validation(format!("invalid value for metadata key '{}'", key))
+            })?;
+
+            headers.insert(name, value);
+        }
+
+        headers.extend(extra_headers(&self.extra_headers)?);
+
+        Ok(headers)
+    }
+}
+
+/// Headers set by the client itself, which extra headers may not override
+const RESERVED_HEADERS: [&str; 6] = [
+    "authorization",
+    "host",
+    "content-length",
+    "x-amz-date",
+    "x-amz-content-sha256",
+    "x-amz-security-token",
+];
+
+/// Validate headers passed through as is. With HMAC auth, `x-amz-*` headers
+/// are signed along with the request; others are sent unsigned.
+pub(crate) fn extra_headers(extra: &HashMap<String, String>) -> Result<HeaderMap, CosError> {
+    let mut headers = HeaderMap::new();
+
+    for (name, value) in extra {
+        let header = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| CosError::Validation(format!("invalid header name '{}'", name)))?;
+
+        if RESERVED_HEADERS.contains(&header.as_str()) {
+            return Err(CosError::Validation(format!(
+                "header '{}' is set by the client and can't be overridden",
+                name
+            )));
+        }
+
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| CosError::Validation(format!("invalid value for header '{}'", name)))?;
+
+        headers.insert(header, value);
+    }
+
+    Ok(headers)
+}
+
+/// Preconditions for reading an object.
+///
+/// A failed `if_match` is reported as `CosError::PreconditionFailed`; an object
+/// matching `if_none_match` or unchanged since `if_modified_since` as
+/// `CosError::NotModified`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conditions {
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl Conditions {
+    pub(crate) fn headers(&self) -> Result<HeaderMap, CosError> {
+        let mut headers = HeaderMap::new();
+
+        if let Some(etag) = &self.if_match {
+            headers.insert(IF_MATCH, etag_header(etag)?);
+        }
+
+        if let Some(etag) = &self.if_none_match {
+            headers.insert(IF_NONE_MATCH, etag_header(etag)?);
+        }
+
+        if let Some(date) = &self.if_modified_since {
+            let date = date.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            headers.insert(
+                IF_MODIFIED_SINCE,
+                HeaderValue::from_str(&date).expect("HTTP dates are valid header values"),
+            );
+        }
+
+        Ok(headers)
+    }
+}
+
+/// An ETag as sent in `If-Match`/`If-None-Match`, quoted unless it is `*`.
+fn etag_header(etag: &str) -> Result<HeaderValue, CosError> {
+    let value = if etag == "*" || etag.starts_with('"') {
+        etag.to_string()
+    } else {
+        format!("\"{}\"", etag)
+    };
+
+    HeaderValue::from_str(&value)
+        .map_err(|_| CosError::Validation(format!("invalid ETag '{}'", etag)))
+}
+
+/// The result of a successful upload.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PutObjectOutput {
+    pub etag: Option<String>,
+    /// Set when versioning is enabled on the bucket
+    pub version_id: Option<String>,
+    /// Size of the uploaded body, when it was known before sending; streamed
+    /// bodies of unknown length leave this unset
+    pub bytes: Option<u64>,
+    pub context: RequestContext,
+}
+
+impl PutObjectOutput {
+    pub(crate) fn from_headers(headers: &HeaderMap, bytes: Option<u64>) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        Self {
+            etag: get(reqwest::header::ETAG.as_str()).map(|e| unquote_etag(&e).to_string()),
+            version_id: get("x-amz-version-id"),
+            bytes,
+            context: RequestContext::from_headers(headers),
+        }
+    }
+}
+
+/// The result of a server-side copy.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CopyObjectResult {
+    #[serde(rename = "$unflatten=ETag", deserialize_with = "deserialize_etag")]
+    pub etag: String,
+    #[serde(rename = "$unflatten=LastModified")]
+    pub last_modified: String,
+}
+
+/// An object body along with the metadata from its response headers.
+pub struct GetObjectOutput {
+    pub metadata: ObjectMetadata,
+    /// The bytes served for a ranged request; `None` for a whole object
+    pub range: Option<ContentRange>,
+    pub body: Box<dyn Read>,
+}
+
+impl Read for GetObjectOutput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+/// The bytes served for a ranged GET, parsed from `Content-Range`, e.g. `bytes 0-99/1234`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    pub start: u64,
+    /// Inclusive
+    pub end: u64,
+    /// Size of the whole object, unless the server reported it as unknown
+    pub total: Option<u64>,
+}
+
+impl FromStr for ContentRange {
+    type Err = CosError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CosError::Validation(format!("invalid Content-Range '{}'", s));
+
+        let (range, total) = s
+            .strip_prefix("bytes ")
+            .and_then(|r| r.split_once('/'))
+            .ok_or_else(invalid)?;
+        let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+
+        Ok(ContentRange {
+            start: start.parse().map_err(|_| invalid())?,
+            end: end.parse().map_err(|_| invalid())?,
+            total: match total {
+                "*" => None,
+                t => Some(t.parse().map_err(|_| invalid())?),
+            },
+        })
+    }
+}
+
+/// A ranged object body along with the range that was actually served.
+pub struct GetObjectRangeOutput {
+    /// `None` when the server ignored the range and sent the whole object
+    pub range: Option<ContentRange>,
+    pub body: Box<dyn Read>,
+}
+
+impl Read for GetObjectRangeOutput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl Client {
+    pub fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata, Error> {
+        self.head_object_version(bucket, key, None)
+    }
+
+    /// Like `head_object`, failing with `CosError::NotModified` or
+    /// `CosError::PreconditionFailed` when `conditions` don't hold.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn head_object_with_conditions(
+        &self,
+        bucket: &str,
+        key: &str,
+        conditions: &Conditions,
+    ) -> Result<ObjectMetadata, Error> {
+        let c = &self.client;
+        let url = format!("{}{}", self.bucket_url(bucket), key);
+
+        let response = c.head(url).headers(conditions.headers()?).send_via(self)?;
+
+        let r = check_response(response)?;
+        Ok(ObjectMetadata::from_headers(r.headers()))
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn object_exists(&self, bucket: &str, key: &str) -> Result<bool, Error> {
+        let c = &self.client;
+        let url = format!("{}{}", self.bucket_url(bucket), key);
+
+        let response = c.head(url).send_via(self)?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        check_response(response)?;
+        Ok(true)
+    }
+
+    pub fn get_object_with_metadata(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<GetObjectOutput, Error> {
+        GetObjectRequest::new(bucket, key).send(self)
+    }
+
+    /// Download a whole object into memory.
+    pub fn get_object_bytes(&self, bucket: &str, key: &str) -> Result<Vec<u8>, Error> {
+        Ok(self.get_object_bytes_with_limit(bucket, key, None)?.0)
+    }
+
+    /// Download a whole object into memory as UTF-8 text.
+    pub fn get_object_string(&self, bucket: &str, key: &str) -> Result<String, Error> {
+        Ok(String::from_utf8(self.get_object_bytes(bucket, key)?)?)
+    }
+
+    /// Download a whole object into memory, with its metadata. Objects over
+    /// `max_size` bytes fail with `CosError::BodyTooLarge` instead of being buffered.
+    pub fn get_object_bytes_with_limit(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_size: Option<u64>,
+    ) -> Result<(Vec<u8>, ObjectMetadata), Error> {
+        let obj = self.get_object_with_metadata(bucket, key)?;
+
+        let limit = max_size.unwrap_or(u64::MAX);
+        if obj.metadata.content_length > limit {
+            return Err(CosError::BodyTooLarge(limit).into());
+        }
+
+        let mut buf = Vec::with_capacity(obj.metadata.content_length as usize);
+        let metadata = obj.metadata;
+
+        // the length header may be missing, so enforce the limit while reading too
+        obj.body
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut buf)?;
+        if buf.len() as u64 > limit {
+            return Err(CosError::BodyTooLarge(limit).into());
+        }
+
+        Ok((buf, metadata))
+    }
+
+    /// Download an object and check its MD5 against the ETag.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn verify_object(&self, bucket: &str, key: &str) -> Result<Verification, Error> {
+        let mut obj = self.get_object_with_metadata(bucket, key)?;
+
+        let etag = match obj.metadata.etag.take() {
+            Some(e) if is_md5_etag(&e) => e,
+            _ => return Ok(Verification::NotSimpleMd5),
+        };
+
+        let mut hasher = Md5::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = obj.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        if hex::encode(hasher.finalize()) == etag.to_ascii_lowercase() {
+            Ok(Verification::Match)
+        } else {
+            Ok(Verification::Mismatch)
+        }
+    }
+
+    /// Copy an object within COS without downloading it.
+    #[instrument(
+        skip_all,
+        fields(bucket = dst_bucket, key = dst_key, src_bucket = src_bucket, src_key = src_key)
+    )]
+    pub fn copy_object(
+        &self,
+        src_bucket: &str,
+        src_key: &str,
+        dst_bucket: &str,
+        dst_key: &str,
+    ) -> Result<CopyObjectResult, Error> {
+        let c = &self.client;
+        let url = format!("{}{}", self.bucket_url(dst_bucket), dst_key);
+        let source = format!("/{}/{}", src_bucket, encode(src_key).replace("%2F", "/"));
+
+        let response = c
+            .put(url)
+            .header("x-amz-copy-source", source)
+            .send_via(self)?;
+
+        // a copy can fail after COS has already answered 200
+        let text = check_response_text(response)?;
+        Ok(from_str(&text)?)
+    }
+
+    /// Move an object by copying it and then deleting the source.
+    ///
+    /// The source is only deleted once the copy succeeded. If that delete fails,
+    /// the error is a `CosError::SourceNotDeleted` and the object exists in both places.
+    #[instrument(
+        skip_all,
+        fields(bucket = dst_bucket, key = dst_key, src_bucket = src_bucket, src_key = src_key)
+    )]
+    pub fn move_object(
+        &self,
+        src_bucket: &str,
+        src_key: &str,
+        dst_bucket: &str,
+        dst_key: &str,
+    ) -> Result<(), Error> {
+        self.copy_object(src_bucket, src_key, dst_bucket, dst_key)?;
+
+        self.delete_object(src_bucket, src_key).map_err(|e| {
+            CosError::SourceNotDeleted {
+                bucket: src_bucket.to_string(),
+                key: src_key.to_string(),
+                source: into_send_error(e),
+            }
+            .into()
+        })
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn put_object_with_options<B: Into<Body>>(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: B,
+        options: &PutObjectOptions,
+    ) -> Result<PutObjectOutput, Error> {
+        let mut headers = options.headers()?;
+
+        let body: Body = body.into();
+        let bytes = body.as_bytes().map(|b| b.len() as u64);
+        if let Some(n) = bytes {
+            check_put_size(n)?;
+        }
+
+        let checksum = match (options.checksum, body.as_bytes()) {
+            (Some(alg), Some(b)) => Some((alg, alg.compute(b))),
+            (Some(_), None) => {
+                return Err(CosError::Validation(
+                    "a checksum requires the body to be in memory".to_string(),
+                )
+                .into())
+            }
+            (None, _) => None,
+        };
+
+        if let Some((alg, value)) = &checksum {
+            headers.insert(
+                alg.header_name(),
+                HeaderValue::from_str(value).expect("base64 is a valid header value"),
+            );
+        }
+
+        let c = &self.client;
+        let url = format!("{}{}", self.bucket_url(bucket), key);
+
+        let mut req = c.put(url).headers(headers).body(body);
+        if let Some(t) = self.upload_timeout {
+            req = req.timeout(t);
+        }
+
+        let response = req.send_via(self)?;
+
+        let r = check_response(response)?;
+        if let Some((alg, value)) = &checksum {
+            verify_echoed(r.headers(), *alg, value)?;
+        }
+
+        Ok(PutObjectOutput::from_headers(r.headers(), bytes))
+    }
+}
+
+/// Builder for uploads with optional headers, e.g.
+/// `PutObjectRequest::new(bucket, key).body(b).content_type("text/html").send(&client)`.
+pub struct PutObjectRequest {
+    bucket: String,
+    key: String,
+    body: Option<Body>,
+    options: PutObjectOptions,
+}
+
+impl PutObjectRequest {
+    pub fn new(bucket: &str, key: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            body: None,
+            options: PutObjectOptions::default(),
+        }
+    }
+
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Add a user metadata entry, sent as an `x-amz-meta-{key}` header.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.options
+            .metadata
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn storage_class(mut self, class: StorageClass) -> Self {
+        self.options.storage_class = Some(class);
+        self
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.options.content_type = Some(content_type.to_string());
+        self
+    }
+
+    pub fn content_disposition(mut self, disposition: &str) -> Self {
+        self.options.content_disposition = Some(disposition.to_string());
+        self
+    }
+
+    pub fn content_encoding(mut self, encoding: &str) -> Self {
+        self.options.content_encoding = Some(encoding.to_string());
+        self
+    }
+
+    pub fn cache_control(mut self, cache_control: &str) -> Self {
+        self.options.cache_control = Some(cache_control.to_string());
+        self
+    }
+
+    pub fn acl(mut self, acl: CannedAcl) -> Self {
+        self.options.acl = Some(acl);
+        self
+    }
+
+    pub fn retention(mut self, mode: RetentionMode, retain_until: DateTime<Utc>) -> Self {
+        self.options.retention = Some(ObjectRetention { mode, retain_until });
+        self
+    }
+
+    /// Fail with `CosError::PreconditionFailed` instead of overwriting an existing object.
+    pub fn if_not_exists(mut self) -> Self {
+        self.options.if_none_match = Some("*".to_string());
+        self
+    }
+
+    pub fn legal_hold(mut self, on: bool) -> Self {
+        self.options.legal_hold = Some(on);
+        self
+    }
+
+    /// Send a header the builder doesn't model; reserved headers like
+    /// `Authorization` are rejected when the request is sent.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.options
+            .extra_headers
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Encrypt the object with the Key Protect or HPCS root key `root_key_crn`.
+    pub fn key_protect(mut self, root_key_crn: &str) -> Self {
+        self.options.key_protect = Some(KeyProtect::new(root_key_crn));
+        self
+    }
+
+    /// Have COS verify the body against a checksum computed before sending.
+    pub fn checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.options.checksum = Some(algorithm);
+        self
+    }
+
+    pub fn options(&self) -> &PutObjectOptions {
+        &self.options
+    }
+
+    pub fn send(self, client: &Client) -> Result<PutObjectOutput, Error> {
+        let body = self.body.unwrap_or_else(|| Body::from(Vec::new()));
+        client.put_object_with_options(&self.bucket, &self.key, body, &self.options)
+    }
+}
+
+/// Builder for downloads with optional request settings.
+pub struct GetObjectRequest {
+    bucket: String,
+    key: String,
+    range: Option<(u64, Option<u64>)>,
+    version_id: Option<String>,
+    conditions: Conditions,
+    extra_headers: HashMap<String, String>,
+    verify_checksum: bool,
+}
+
+impl GetObjectRequest {
+    pub fn new(bucket: &str, key: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            range: None,
+            version_id: None,
+            conditions: Conditions::default(),
+            extra_headers: HashMap::new(),
+            verify_checksum: false,
+        }
+    }
+
+    /// Fetch a specific version of the object instead of the latest.
+    pub fn version_id(mut self, version_id: &str) -> Self {
+        self.version_id = Some(version_id.to_string());
+        self
+    }
+
+    /// Only fetch bytes `start..=end`, or from `start` to the end of the object.
+    pub fn range(mut self, start: u64, end: Option<u64>) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    /// Only fetch the object if its ETag matches, else fail with `CosError::PreconditionFailed`.
+    pub fn if_match(mut self, etag: &str) -> Self {
+        self.conditions.if_match = Some(etag.to_string());
+        self
+    }
+
+    /// Fail with `CosError::NotModified` if the object's ETag matches, e.g. a cached copy's.
+    pub fn if_none_match(mut self, etag: &str) -> Self {
+        self.conditions.if_none_match = Some(etag.to_string());
+        self
+    }
+
+    /// Fail with `CosError::NotModified` if the object is unchanged since `date`.
+    pub fn if_modified_since(mut self, date: DateTime<Utc>) -> Self {
+        self.conditions.if_modified_since = Some(date);
+        self
+    }
+
+    /// Send a header the builder doesn't model; reserved headers like
+    /// `Authorization` are rejected when the request is sent.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.extra_headers
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Check the body against the checksum stored with the object, if it has
+    /// one. A mismatch fails the read that reaches the end of the body.
+    pub fn verify_checksum(mut self) -> Self {
+        self.verify_checksum = true;
+        self
+    }
+
+    #[instrument(name = "get_object", skip_all, fields(bucket = self.bucket.as_str(), key = self.key.as_str()))]
+    pub fn send(self, client: &Client) -> Result<GetObjectOutput, Error> {
+        let c = &client.client;
+        let url = object_url(
+            &client.bucket_url(&self.bucket),
+            &self.key,
+            self.version_id.as_deref(),
+        );
+
+        let mut req = c
+            .get(url)
+            .headers(self.conditions.headers()?)
+            .headers(extra_headers(&self.extra_headers)?);
+
+        if let Some((start, end)) = self.range {
+            let end_str = end.map(|e| e.to_string()).unwrap_or_default();
+            req = req.header("Range", format!("bytes={}-{}", start, end_str));
+        }
+
+        if self.verify_checksum {
+            req = req.header("x-amz-checksum-mode", "ENABLED");
+        }
+
+        if let Some(t) = client.download_timeout {
+            req = req.timeout(t);
+        }
+
+        let r = check_response(req.send_via(client)?)?;
+        let metadata = ObjectMetadata::from_headers(r.headers());
+        let range = r
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+
+        // a range covers only part of the content the checksum was computed over
+        let checksum = match self.range {
+            None if self.verify_checksum => checksum_from_headers(r.headers()),
+            _ => None,
+        };
+
+        let body: Box<dyn Read> = match checksum {
+            Some((alg, expected)) => Box::new(ChecksumReader::new(r, alg, expected)),
+            None => Box::new(r),
+        };
+
+        Ok(GetObjectOutput {
+            metadata,
+            range,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_headers_round_trip() {
+        let mut options = PutObjectOptions::default();
+        options
+            .metadata
+            .insert("source".to_string(), "camera-01".to_string());
+
+        let headers = options.headers().unwrap();
+        assert_eq!(headers["x-amz-meta-source"], "camera-01");
+
+        let meta = ObjectMetadata::from_headers(&headers);
+        assert_eq!(meta.metadata, options.metadata);
+    }
+
+    #[test]
+    fn test_metadata_validation() {
+        let mut options = PutObjectOptions::default();
+        options
+            .metadata
+            .insert("bad key".to_string(), "value".to_string());
+        assert!(options.headers().is_err());
+
+        let mut options = PutObjectOptions::default();
+        options
+            .metadata
+            .insert("key".to_string(), "two\nlines".to_string());
+        assert!(options.headers().is_err());
+    }
+
+    #[test]
+    fn test_storage_class() {
+        assert_eq!(
+            "standard".parse::<StorageClass>().unwrap(),
+            StorageClass::Standard
+        );
+        assert_eq!(
+            "GLACIER".parse::<StorageClass>().unwrap(),
+            StorageClass::Glacier
+        );
+        assert!("STANDRAD".parse::<StorageClass>().is_err());
+
+        let options = PutObjectOptions {
+            storage_class: Some(StorageClass::Vault),
+            ..Default::default()
+        };
+        assert_eq!(options.headers().unwrap()["x-amz-storage-class"], "VAULT");
+    }
+
+    #[test]
+    fn test_unquote_etag() {
+        assert_eq!(
+            unquote_etag("\"d41d8cd98f00b204e9800998ecf8427e\""),
+            "d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert_eq!(unquote_etag("\"abc-3\""), "abc-3");
+        assert_eq!(unquote_etag("abc"), "abc");
+        assert_eq!(unquote_etag("\""), "\"");
+    }
+
+    #[test]
+    fn test_is_md5_etag() {
+        assert!(is_md5_etag("d41d8cd98f00b204e9800998ecf8427e"));
+        assert!(!is_md5_etag("d41d8cd98f00b204e9800998ecf8427e-3"));
+        assert!(!is_md5_etag(""));
+    }
+
+    #[test]
+    fn test_content_headers() {
+        let options = PutObjectOptions {
+            content_type: content_type_from_extension("site/index.HTML").map(String::from),
+            cache_control: Some("max-age=3600".to_string()),
+            ..Default::default()
+        };
+
+        let headers = options.headers().unwrap();
+        assert_eq!(headers[CONTENT_TYPE], "text/html");
+        assert_eq!(headers[CACHE_CONTROL], "max-age=3600");
+        assert!(headers.get(CONTENT_ENCODING).is_none());
+
+        assert_eq!(content_type_from_extension("noext"), None);
+    }
+
+    #[test]
+    fn test_put_object_request_options() {
+        let req = PutObjectRequest::new("bucket", "key")
+            .content_type("application/json")
+            .metadata("owner", "team-a")
+            .storage_class(StorageClass::Smart)
+            .acl(CannedAcl::PublicRead)
+            .retention(
+                RetentionMode::Governance,
+                "2030-01-01T00:00:00Z".parse().unwrap(),
+            )
+            .legal_hold(true);
+
+        let headers = req.options().headers().unwrap();
+        assert_eq!(headers["x-amz-acl"], "public-read");
+        assert_eq!(headers["x-amz-object-lock-mode"], "GOVERNANCE");
+        assert_eq!(
+            headers["x-amz-object-lock-retain-until-date"],
+            "2030-01-01T00:00:00Z"
+        );
+        assert_eq!(headers["x-amz-object-lock-legal-hold"], "ON");
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+        assert_eq!(headers["x-amz-meta-owner"], "team-a");
+        assert_eq!(headers["x-amz-storage-class"], "SMART");
+    }
+
+    #[test]
+    fn test_move_object() {
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(200, "<CopyObjectResult><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>\"abc\"</ETag></CopyObjectResult>")
+            .respond(204, "");
+
+        client.move_object("src", "a dir/x+1", "dst", "y").unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].url, "https://dst.s3.example.com/y");
+        assert_eq!(
+            requests[0].headers["x-amz-copy-source"],
+            "/src/a%20dir/x%2B1"
+        );
+        assert_eq!(requests[1].method, reqwest::Method::DELETE);
+        assert_eq!(requests[1].url, "https://src.s3.example.com/a%20dir/x+1");
+    }
+
+    #[test]
+    fn test_move_object_delete_fails() {
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(200, "<CopyObjectResult><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>\"abc\"</ETag></CopyObjectResult>")
+            .respond(403, "<Error><Code>AccessDenied</Code></Error>");
+
+        let err = client.move_object("src", "x", "dst", "y").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::SourceNotDeleted { bucket, key, .. }) if bucket == "src" && key == "x"
+        ));
+
+        // a failed copy leaves the source alone
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(404, "<Error><Code>NoSuchKey</Code></Error>");
+        assert!(client.move_object("src", "x", "dst", "y").is_err());
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_copy_object_error_with_200() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(
+            200,
+            "<Error><Code>SlowDown</Code><Message>Reduce your request rate.</Message></Error>",
+        );
+
+        let err = client.move_object("src", "x", "dst", "y").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::Api(e)) if e.code.as_deref() == Some("SlowDown")
+        ));
+        // the source is kept since the copy failed
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_extra_headers() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, "").respond(200, "data");
+
+        PutObjectRequest::new("bucket", "k")
+            .header("x-amz-request-payer", "requester")
+            .send(&client)
+            .unwrap();
+        GetObjectRequest::new("bucket", "k")
+            .header("x-vendor-trace", "1")
+            .send(&client)
+            .unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].headers["x-amz-request-payer"], "requester");
+        assert_eq!(requests[1].headers["x-vendor-trace"], "1");
+
+        let err = GetObjectRequest::new("bucket", "k")
+            .header("Authorization", "Bearer other")
+            .send(&client)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::Validation(_))
+        ));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_put_object_output() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond_with_headers(
+            200,
+            vec![
+                ("etag", "\"abc\"".to_string()),
+                ("x-amz-version-id", "v1".to_string()),
+            ],
+            "",
+        );
+
+        let out = client.put_object("b", "k", "hello").unwrap();
+        assert_eq!(out.etag.as_deref(), Some("abc"));
+        assert_eq!(out.version_id.as_deref(), Some("v1"));
+        assert_eq!(out.bytes, Some(5));
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(304, "")
+            .respond(412, "<Error><Code>PreconditionFailed</Code></Error>");
+
+        let err = GetObjectRequest::new("b", "k")
+            .if_none_match("abc")
+            .if_modified_since("2023-01-02T03:04:05Z".parse().unwrap())
+            .send(&client)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::NotModified)
+        ));
+
+        let err = PutObjectRequest::new("b", "k")
+            .body("x")
+            .if_not_exists()
+            .send(&client)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::PreconditionFailed(_))
+        ));
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].headers["if-none-match"], "\"abc\"");
+        assert_eq!(
+            requests[0].headers["if-modified-since"],
+            "Mon, 02 Jan 2023 03:04:05 GMT"
+        );
+        assert_eq!(requests[1].headers["if-none-match"], "*");
+    }
+
+    #[test]
+    fn test_content_range() {
+        assert_eq!(
+            "bytes 0-99/1234".parse::<ContentRange>().unwrap(),
+            ContentRange {
+                start: 0,
+                end: 99,
+                total: Some(1234)
+            }
+        );
+        assert_eq!(
+            "bytes 100-199/*".parse::<ContentRange>().unwrap().total,
+            None
+        );
+        assert!("bytes */1234".parse::<ContentRange>().is_err());
+        assert!("items 0-1/2".parse::<ContentRange>().is_err());
+
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond_with_headers(
+            206,
+            vec![("content-range", "bytes 2-4/10".to_string())],
+            "abc",
+        );
+
+        let mut out = client.get_object_at_range("b", "k", 2, Some(4)).unwrap();
+        assert_eq!(out.range.unwrap().total, Some(10));
+        let mut body = String::new();
+        out.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "abc");
+        assert_eq!(transport.requests()[0].headers["range"], "bytes=2-4");
+    }
+
+    #[test]
+    fn test_put_object_checksum() {
+        let (client, transport) = crate::transport::mock::client();
+        let sum = ChecksumAlgorithm::Crc32c.compute(b"data");
+        transport
+            .respond_with_headers(200, vec![("x-amz-checksum-crc32c", sum.clone())], "")
+            .respond_with_headers(
+                200,
+                vec![("x-amz-checksum-crc32c", "AAAAAA==".to_string())],
+                "",
+            );
+
+        PutObjectRequest::new("b", "k")
+            .body("data")
+            .checksum(ChecksumAlgorithm::Crc32c)
+            .send(&client)
+            .unwrap();
+
+        let req = &transport.requests()[0];
+        assert_eq!(req.headers["x-amz-checksum-algorithm"], "CRC32C");
+        assert_eq!(req.headers["x-amz-checksum-crc32c"], sum.as_str());
+
+        let err = PutObjectRequest::new("b", "k")
+            .body("data")
+            .checksum(ChecksumAlgorithm::Crc32c)
+            .send(&client)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_get_object_verify_checksum() {
+        let (client, transport) = crate::transport::mock::client();
+        let sum = ChecksumAlgorithm::Sha256.compute(b"data");
+        transport
+            .respond_with_headers(200, vec![("x-amz-checksum-sha256", sum)], "data")
+            .respond_with_headers(
+                200,
+                vec![("x-amz-checksum-sha256", "bad".to_string())],
+                "data",
+            );
+
+        let mut body = String::new();
+        GetObjectRequest::new("b", "k")
+            .verify_checksum()
+            .send(&client)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "data");
+        assert_eq!(
+            transport.requests()[0].headers["x-amz-checksum-mode"],
+            "ENABLED"
+        );
+
+        let mut out = GetObjectRequest::new("b", "k")
+            .verify_checksum()
+            .send(&client)
+            .unwrap();
+        assert!(out.read_to_string(&mut body).is_err());
+    }
+
+    #[test]
+    fn test_get_object_bytes() {
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(200, "hello")
+            .respond(200, "hello")
+            .respond_with_headers(200, vec![("content-length", "5".to_string())], "hello");
+
+        assert_eq!(client.get_object_string("b", "k").unwrap(), "hello");
+
+        let (bytes, _) = client
+            .get_object_bytes_with_limit("b", "k", Some(5))
+            .unwrap();
+        assert_eq!(bytes, b"hello");
+
+        let err = client
+            .get_object_bytes_with_limit("b", "k", Some(4))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::BodyTooLarge(4))
+        ));
+    }
+
+    #[test]
+    fn test_key_protect_headers() {
+        let crn = "crn:v1:bluemix:public:kms:us-south:a/f047b55a:3a6b2c9d:key:12e8c9c2";
+
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, "").respond_with_headers(
+            200,
+            vec![(KP_ROOT_KEY_CRN, crn.to_string())],
+            "",
+        );
+
+        PutObjectRequest::new("b", "k")
+            .key_protect(crn)
+            .send(&client)
+            .unwrap();
+
+        let req = &transport.requests()[0];
+        assert_eq!(req.headers[KP_ALGORITHM], "AES256");
+        assert_eq!(req.headers[KP_ROOT_KEY_CRN], crn);
+
+        let meta = client.head_object("b", "k").unwrap();
+        assert_eq!(meta.kp_root_key_crn.as_deref(), Some(crn));
+
+        for bad in [
+            "crn:v1:bluemix:public:kms:us-south:a/f047b55a:3a6b2c9d:key:",
+            "crn:v1:bluemix:public:cloud-object-storage:global:a/f047b55a:3a6b2c9d:key:1",
+            "not-a-crn",
+        ] {
+            let options = PutObjectOptions {
+                key_protect: Some(KeyProtect::new(bad)),
+                ..Default::default()
+            };
+            assert!(options.headers().is_err(), "{}", bad);
+        }
+    }
+}
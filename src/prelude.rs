@@ -0,0 +1,24 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The commonly used types, for `use ibmcloud_cos::prelude::*;`.
+
+pub use crate::checksum::ChecksumAlgorithm;
+pub use crate::cos::{Bucket, Client, Contents, ContentsFilter, Error};
+pub use crate::credentials::{CredentialProvider, IamTokenProvider};
+pub use crate::endpoint::AddressingStyle;
+pub use crate::error::CosError;
+pub use crate::hmac::Client as HmacClient;
+pub use crate::multipartupload::{CompleteMultipartUpload, CompletedUpload, Part};
+pub use crate::object::{GetObjectRequest, PutObjectOptions, PutObjectRequest, StorageClass};
@@ -0,0 +1,88 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Read};
+use std::sync::Arc;
+
+/// Receives progress updates during a transfer.
+///
+/// Implemented for closures taking `(bytes_so_far, total)`. Progress is shared
+/// with the HTTP client's body, so it must be `Send + Sync`.
+pub trait Progress: Send + Sync {
+    fn on_bytes(&self, transferred: u64, total: Option<u64>);
+}
+
+impl<F: Fn(u64, Option<u64>) + Send + Sync> Progress for F {
+    fn on_bytes(&self, transferred: u64, total: Option<u64>) {
+        self(transferred, total)
+    }
+}
+
+/// A reader that reports the bytes read through it to a [`Progress`].
+///
+/// Wrap the reader passed to `upload_multipart` to follow a multipart upload.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<dyn Progress>,
+    transferred: u64,
+    total: Option<u64>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, total: Option<u64>, progress: Arc<dyn Progress>) -> Self {
+        Self {
+            inner,
+            progress,
+            transferred: 0,
+            total,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if n > 0 {
+            self.transferred += n as u64;
+            self.progress.on_bytes(self.transferred, self.total);
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress_reader() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let s = seen.clone();
+        let progress = Arc::new(move |n, total| s.lock().unwrap().push((n, total)));
+
+        let data = [7u8; 10];
+        let mut r = ProgressReader::new(&data[..], Some(10), progress);
+
+        let mut buf = [0u8; 4];
+        while r.read(&mut buf).unwrap() > 0 {}
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(4, Some(10)), (8, Some(10)), (10, Some(10))]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quick_xml::se::to_string;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::cos::{check_response, Client, Error};
+use crate::transport::SendVia;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreTier {
+    Standard,
+    Bulk,
+    Expedited,
+}
+
+impl RestoreTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestoreTier::Standard => "Standard",
+            RestoreTier::Bulk => "Bulk",
+            RestoreTier::Expedited => "Expedited",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct RestoreRequest {
+    #[serde(rename = "$unflatten=Days")]
+    pub days: u32,
+    #[serde(rename = "GlacierJobParameters")]
+    pub job_parameters: GlacierJobParameters,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct GlacierJobParameters {
+    #[serde(rename = "$unflatten=Tier")]
+    pub tier: String,
+}
+
+/// The state of a restore, parsed from the `x-amz-restore` header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestoreStatus {
+    /// Whether the restore is still in progress
+    pub ongoing: bool,
+    /// When the restored copy expires, once the restore has completed
+    pub expiry_date: Option<String>,
+}
+
+impl RestoreStatus {
+    /// Parse a header value like `ongoing-request="false", expiry-date="Fri, 23 Dec 2012 00:00:00 GMT"`.
+    pub fn parse(header: &str) -> Option<Self> {
+        let ongoing = field(header, "ongoing-request")?;
+
+        Some(Self {
+            ongoing: ongoing == "true",
+            expiry_date: field(header, "expiry-date").map(|d| d.to_string()),
+        })
+    }
+}
+
+fn field<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    let start = header.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = header[start..].find('"')?;
+    Some(&header[start..start + len])
+}
+
+impl Client {
+    /// Start restoring an archived object so it can be read for `days` days.
+    ///
+    /// Use `head_object` and `ObjectMetadata::restore_status` to follow progress.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn restore_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        days: u32,
+        tier: RestoreTier,
+    ) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}{}?restore", self.bucket_url(bucket), key);
+
+        let payload = to_string(&RestoreRequest {
+            days,
+            job_parameters: GlacierJobParameters {
+                tier: tier.as_str().to_string(),
+            },
+        })?;
+
+        let response = c.post(url).body(payload).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_request_xml() {
+        let req = RestoreRequest {
+            days: 3,
+            job_parameters: GlacierJobParameters {
+                tier: RestoreTier::Bulk.as_str().to_string(),
+            },
+        };
+
+        let exp = "<RestoreRequest><Days>3</Days><GlacierJobParameters><Tier>Bulk</Tier></GlacierJobParameters></RestoreRequest>";
+        assert_eq!(to_string(&req).unwrap(), exp);
+    }
+
+    #[test]
+    fn test_restore_status_parse() {
+        assert_eq!(
+            RestoreStatus::parse(r#"ongoing-request="true""#),
+            Some(RestoreStatus {
+                ongoing: true,
+                expiry_date: None
+            })
+        );
+
+        assert_eq!(
+            RestoreStatus::parse(
+                r#"ongoing-request="false", expiry-date="Fri, 23 Dec 2012 00:00:00 GMT""#
+            ),
+            Some(RestoreStatus {
+                ongoing: false,
+                expiry_date: Some("Fri, 23 Dec 2012 00:00:00 GMT".to_string())
+            })
+        );
+
+        assert_eq!(RestoreStatus::parse("garbage"), None);
+    }
+}
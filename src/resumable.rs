@@ -0,0 +1,203 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Read};
+
+use tracing::{instrument, warn};
+
+use crate::cos::{Client, Error};
+use crate::error::into_send_error;
+use crate::object::GetObjectRequest;
+
+/// An object body that picks up where it left off when the stream breaks.
+///
+/// On a read error, or if the body ends short of the object's length, the rest
+/// is requested again with a ranged GET from the last byte read, up to
+/// `max_retries` times over the whole download. Each resumed GET must match
+/// the ETag of the first, so a changed object fails the read rather than
+/// splicing two versions together.
+pub struct ResumableReader {
+    client: Client,
+    bucket: String,
+    key: String,
+    etag: Option<String>,
+    body: Box<dyn Read>,
+    offset: u64,
+    length: u64,
+    retries_left: u32,
+}
+
+impl ResumableReader {
+    /// Bytes read so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Size of the whole object.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn resume(&mut self) -> Result<(), Error> {
+        let mut req = GetObjectRequest::new(&self.bucket, &self.key).range(self.offset, None);
+        if let Some(etag) = &self.etag {
+            req = req.if_match(etag);
+        }
+        let output = req.send(&self.client)?;
+
+        match output.range {
+            Some(range) if range.start == self.offset => {}
+            Some(range) => {
+                return Err(format!(
+                    "resumed at byte {} but asked for byte {}",
+                    range.start, self.offset
+                )
+                .into())
+            }
+            None => {
+                return Err(format!(
+                    "asked to resume at byte {} but the whole object was sent",
+                    self.offset
+                )
+                .into())
+            }
+        }
+
+        self.body = output.body;
+        Ok(())
+    }
+}
+
+impl Read for ResumableReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let err = match self.body.read(buf) {
+                Ok(0) if !buf.is_empty() && self.offset < self.length => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("body ended at byte {} of {}", self.offset, self.length),
+                ),
+                Ok(n) => {
+                    self.offset += n as u64;
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => e,
+            };
+
+            if self.retries_left == 0 {
+                return Err(err);
+            }
+            self.retries_left -= 1;
+
+            warn!(
+                "download of {}/{} broke at byte {}, resuming: {}",
+                self.bucket, self.key, self.offset, err
+            );
+
+            self.resume()
+                .map_err(|e| io::Error::other(into_send_error(e)))?;
+        }
+    }
+}
+
+impl Client {
+    /// Like `get_object`, but the returned reader resumes a broken download
+    /// with ranged GETs, retrying up to `max_retries` times.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn get_object_resumable(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_retries: u32,
+    ) -> Result<ResumableReader, Error> {
+        let output = GetObjectRequest::new(bucket, key).send(self)?;
+
+        Ok(ResumableReader {
+            client: self.clone(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            etag: output.metadata.etag,
+            body: output.body,
+            offset: 0,
+            length: output.metadata.content_length,
+            retries_left: max_retries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock;
+
+    #[test]
+    fn test_resumes_short_body() {
+        let (client, transport) = mock::client();
+        transport
+            .respond_with_headers(
+                200,
+                vec![
+                    ("content-length", "6".to_string()),
+                    ("etag", "\"abc123\"".to_string()),
+                ],
+                "abc",
+            )
+            .respond_with_headers(
+                206,
+                vec![("content-range", "bytes 3-5/6".to_string())],
+                "def",
+            );
+
+        let mut r = client.get_object_resumable("bucket", "key", 1).unwrap();
+        let mut body = String::new();
+        r.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "abcdef");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].headers["range"], "bytes=3-");
+        assert_eq!(requests[1].headers["if-match"], "\"abc123\"");
+    }
+
+    #[test]
+    fn test_rejects_unranged_resume() {
+        let (client, transport) = mock::client();
+        transport
+            .respond_with_headers(200, vec![("content-length", "6".to_string())], "abc")
+            .respond_with_headers(200, vec![("content-length", "6".to_string())], "abcdef");
+
+        let mut r = client.get_object_resumable("bucket", "key", 1).unwrap();
+        let mut body = Vec::new();
+        assert!(r.read_to_end(&mut body).is_err());
+        assert_eq!(body, b"abc");
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let (client, transport) = mock::client();
+        transport
+            .respond_with_headers(200, vec![("content-length", "6".to_string())], "abc")
+            .respond_with_headers(206, vec![("content-range", "bytes 3-5/6".to_string())], "");
+
+        let mut r = client.get_object_resumable("bucket", "key", 1).unwrap();
+        let mut body = Vec::new();
+        let err = r.read_to_end(&mut body).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(r.offset(), 3);
+    }
+}
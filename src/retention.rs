@@ -0,0 +1,200 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::checksum::content_md5;
+use crate::cos::{check_response, parse_timestamp, read_checked, Client, Error};
+use crate::error::CosError;
+use crate::transport::SendVia;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionMode {
+    Compliance,
+    Governance,
+}
+
+impl RetentionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetentionMode::Compliance => "COMPLIANCE",
+            RetentionMode::Governance => "GOVERNANCE",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, CosError> {
+        match s {
+            "COMPLIANCE" => Ok(RetentionMode::Compliance),
+            "GOVERNANCE" => Ok(RetentionMode::Governance),
+            _ => Err(CosError::Validation(format!(
+                "unknown retention mode '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// An object lock retention period; the object can't be overwritten or
+/// deleted until `retain_until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectRetention {
+    pub mode: RetentionMode,
+    pub retain_until: DateTime<Utc>,
+}
+
+pub(crate) fn format_retain_until(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename = "Retention")]
+struct Retention {
+    #[serde(rename = "$unflatten=Mode")]
+    mode: String,
+    #[serde(rename = "$unflatten=RetainUntilDate")]
+    retain_until_date: String,
+}
+
+impl From<&ObjectRetention> for Retention {
+    fn from(r: &ObjectRetention) -> Self {
+        Retention {
+            mode: r.mode.as_str().to_string(),
+            retain_until_date: format_retain_until(&r.retain_until),
+        }
+    }
+}
+
+impl TryFrom<Retention> for ObjectRetention {
+    type Error = Error;
+
+    fn try_from(r: Retention) -> Result<Self, Error> {
+        Ok(ObjectRetention {
+            mode: RetentionMode::parse(&r.mode)?,
+            retain_until: parse_timestamp(&r.retain_until_date)?,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename = "LegalHold")]
+struct LegalHold {
+    /// `ON` or `OFF`
+    #[serde(rename = "$unflatten=Status")]
+    status: String,
+}
+
+impl Client {
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn put_object_retention(
+        &self,
+        bucket: &str,
+        key: &str,
+        retention: &ObjectRetention,
+    ) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}{}?retention", self.bucket_url(bucket), key);
+
+        let payload = to_string(&Retention::from(retention))?;
+
+        let response = c
+            .put(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn get_object_retention(&self, bucket: &str, key: &str) -> Result<ObjectRetention, Error> {
+        let c = &self.client;
+        let url = format!("{}{}?retention", self.bucket_url(bucket), key);
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let retention: Retention = from_str(&text)?;
+
+        retention.try_into()
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn put_object_legal_hold(&self, bucket: &str, key: &str, on: bool) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}{}?legal-hold", self.bucket_url(bucket), key);
+
+        let payload = to_string(&LegalHold {
+            status: if on { "ON" } else { "OFF" }.to_string(),
+        })?;
+
+        let response = c
+            .put(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    /// Whether a legal hold is currently placed on the object.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn get_object_legal_hold(&self, bucket: &str, key: &str) -> Result<bool, Error> {
+        let c = &self.client;
+        let url = format!("{}{}?legal-hold", self.bucket_url(bucket), key);
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let hold: LegalHold = from_str(&text)?;
+
+        Ok(hold.status == "ON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_retention_xml() {
+        let retention = ObjectRetention {
+            mode: RetentionMode::Compliance,
+            retain_until: Utc.with_ymd_and_hms(2030, 1, 2, 3, 4, 5).unwrap(),
+        };
+
+        let exp = "<Retention><Mode>COMPLIANCE</Mode><RetainUntilDate>2030-01-02T03:04:05Z</RetainUntilDate></Retention>";
+        assert_eq!(to_string(&Retention::from(&retention)).unwrap(), exp);
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?><Retention xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Mode>COMPLIANCE</Mode><RetainUntilDate>2030-01-02T03:04:05.000Z</RetainUntilDate></Retention>"#;
+        let parsed: Retention = from_str(input).unwrap();
+        assert_eq!(ObjectRetention::try_from(parsed).unwrap(), retention);
+    }
+
+    #[test]
+    fn test_legal_hold_xml() {
+        let hold = LegalHold {
+            status: "ON".to_string(),
+        };
+        assert_eq!(
+            to_string(&hold).unwrap(),
+            "<LegalHold><Status>ON</Status></LegalHold>"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use tracing::instrument;
+
+use crate::cos::{Client, Error};
+use crate::error::into_send_error;
+use crate::object::GetObjectRequest;
+
+/// An object read with `Read + Seek`, for libraries that need to jump around
+/// a file, e.g. to read a zip's central directory.
+///
+/// Each read after a seek to a new position costs one ranged GET, which then
+/// streams until the next seek; reads continuing from the current position
+/// reuse the open response. Ranged GETs are made with `If-Match` on the ETag
+/// seen when the object was opened, so a replaced object fails rather than
+/// mixing old and new bytes.
+pub struct SeekableObject {
+    client: Client,
+    bucket: String,
+    key: String,
+    etag: Option<String>,
+    length: u64,
+    position: u64,
+    body: Option<Box<dyn Read>>,
+}
+
+impl SeekableObject {
+    /// Size of the object.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn open(&self) -> Result<Box<dyn Read>, Error> {
+        let mut req = GetObjectRequest::new(&self.bucket, &self.key).range(self.position, None);
+        if let Some(etag) = &self.etag {
+            req = req.if_match(etag);
+        }
+
+        Ok(req.send(&self.client)?.body)
+    }
+}
+
+impl Read for SeekableObject {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.length {
+            return Ok(0);
+        }
+
+        let body = match &mut self.body {
+            Some(body) => body,
+            None => {
+                let body = self
+                    .open()
+                    .map_err(|e| io::Error::other(into_send_error(e)))?;
+                self.body.insert(body)
+            }
+        };
+
+        let n = body.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SeekableObject {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.length.checked_add_signed(d),
+            SeekFrom::Current(d) => self.position.checked_add_signed(d),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative or overflowing position",
+            )
+        })?;
+
+        if position != self.position {
+            self.position = position;
+            self.body = None;
+        }
+
+        Ok(position)
+    }
+}
+
+impl Client {
+    /// Open an object for `Read + Seek` access through ranged GETs.
+    ///
+    /// Only a HEAD request is made up front; see [`SeekableObject`] for the
+    /// cost of reading.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn get_object_seekable(&self, bucket: &str, key: &str) -> Result<SeekableObject, Error> {
+        let metadata = self.head_object(bucket, key)?;
+
+        Ok(SeekableObject {
+            client: self.clone(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            etag: metadata.etag,
+            length: metadata.content_length,
+            position: 0,
+            body: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock;
+
+    #[test]
+    fn test_seek_issues_ranged_gets() {
+        let (client, transport) = mock::client();
+        transport
+            .respond_with_headers(
+                200,
+                vec![
+                    ("content-length", "10".to_string()),
+                    ("etag", "\"abc\"".to_string()),
+                ],
+                "",
+            )
+            .respond(206, "6789")
+            .respond(206, "012");
+
+        let mut obj = client.get_object_seekable("bucket", "key").unwrap();
+        assert_eq!(obj.len(), 10);
+
+        assert_eq!(obj.seek(SeekFrom::End(-4)).unwrap(), 6);
+        let mut tail = String::new();
+        obj.read_to_string(&mut tail).unwrap();
+        assert_eq!(tail, "6789");
+
+        obj.seek(SeekFrom::Start(0)).unwrap();
+        let mut head = [0; 3];
+        obj.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"012");
+        assert_eq!(obj.stream_position().unwrap(), 3);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method, reqwest::Method::HEAD);
+        assert_eq!(requests[1].headers["range"], "bytes=6-");
+        assert_eq!(requests[1].headers["if-match"], "\"abc\"");
+        assert_eq!(requests[2].headers["range"], "bytes=0-");
+
+        assert!(obj.seek(SeekFrom::Current(-4)).is_err());
+    }
+}
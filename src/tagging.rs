@@ -0,0 +1,262 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use quick_xml::{de::from_str, se::to_string};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::checksum::content_md5;
+use crate::cos::{check_response, read_checked, Client, Error};
+use crate::error::CosError;
+use crate::transport::SendVia;
+
+pub const MAX_OBJECT_TAGS: usize = 10;
+pub const MAX_BUCKET_TAGS: usize = 1000;
+pub const MAX_TAG_KEY_LEN: usize = 128;
+pub const MAX_TAG_VALUE_LEN: usize = 256;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct Tagging {
+    #[serde(rename = "TagSet")]
+    pub tag_set: TagSet,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct TagSet {
+    #[serde(rename = "Tag", default)]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Tag {
+    #[serde(rename = "$unflatten=Key")]
+    pub key: String,
+    #[serde(rename = "$unflatten=Value")]
+    pub value: String,
+}
+
+impl From<Vec<(String, String)>> for Tagging {
+    fn from(tags: Vec<(String, String)>) -> Self {
+        Tagging {
+            tag_set: TagSet {
+                tags: tags
+                    .into_iter()
+                    .map(|(key, value)| Tag { key, value })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl From<Tagging> for Vec<(String, String)> {
+    fn from(t: Tagging) -> Self {
+        t.tag_set
+            .tags
+            .into_iter()
+            .map(|t| (t.key, t.value))
+            .collect()
+    }
+}
+
+/// Tag keys and values may hold letters, digits, spaces and `+ - = . _ : / @`.
+fn valid_tag_chars(s: &str) -> bool {
+    s.chars()
+        .all(|c| c.is_alphanumeric() || c.is_whitespace() || "+-=._:/@".contains(c))
+}
+
+pub(crate) fn validate_tags(tags: &[(String, String)], max_tags: usize) -> Result<(), CosError> {
+    if tags.len() > max_tags {
+        return Err(CosError::Validation(format!(
+            "{} tags given, at most {} are allowed",
+            tags.len(),
+            max_tags
+        )));
+    }
+
+    let mut seen = HashSet::new();
+
+    for (key, value) in tags {
+        if key.is_empty() || key.chars().count() > MAX_TAG_KEY_LEN {
+            return Err(CosError::Validation(format!(
+                "tag key '{}' must be between 1 and {} characters",
+                key, MAX_TAG_KEY_LEN
+            )));
+        }
+
+        if value.chars().count() > MAX_TAG_VALUE_LEN {
+            return Err(CosError::Validation(format!(
+                "value for tag '{}' must be at most {} characters",
+                key, MAX_TAG_VALUE_LEN
+            )));
+        }
+
+        if !valid_tag_chars(key) || !valid_tag_chars(value) {
+            return Err(CosError::Validation(format!(
+                "tag '{}' may only contain letters, digits, spaces and + - = . _ : / @",
+                key
+            )));
+        }
+
+        if !seen.insert(key) {
+            return Err(CosError::Validation(format!("duplicate tag key '{}'", key)));
+        }
+    }
+
+    Ok(())
+}
+
+impl Client {
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        tags: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        validate_tags(&tags, MAX_OBJECT_TAGS)?;
+
+        let c = &self.client;
+        let url = format!("{}{}?tagging", self.bucket_url(bucket), key);
+
+        let payload = to_string(&Tagging::from(tags))?;
+
+        let response = c.put(url).body(payload).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let c = &self.client;
+        let url = format!("{}{}?tagging", self.bucket_url(bucket), key);
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let tagging: Tagging = from_str(&text)?;
+
+        Ok(tagging.into())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn delete_object_tagging(&self, bucket: &str, key: &str) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}{}?tagging", self.bucket_url(bucket), key);
+
+        let response = c.delete(url).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn put_bucket_tagging(
+        &self,
+        bucket: &str,
+        tags: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        validate_tags(&tags, MAX_BUCKET_TAGS)?;
+
+        let c = &self.client;
+        let url = format!("{}?tagging", self.bucket_url(bucket));
+
+        let payload = to_string(&Tagging::from(tags))?;
+
+        let response = c
+            .put(url)
+            .header("Content-MD5", content_md5(payload.as_bytes()))
+            .body(payload)
+            .send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_bucket_tagging(&self, bucket: &str) -> Result<Vec<(String, String)>, Error> {
+        let c = &self.client;
+        let url = format!("{}?tagging", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let tagging: Tagging = from_str(&text)?;
+
+        Ok(tagging.into())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn delete_bucket_tagging(&self, bucket: &str) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?tagging", self.bucket_url(bucket));
+
+        let response = c.delete(url).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagging_xml() {
+        let tagging = Tagging::from(vec![
+            ("project".to_string(), "atlas".to_string()),
+            ("cost-center".to_string(), "1234".to_string()),
+        ]);
+
+        let exp = "<Tagging><TagSet><Tag><Key>project</Key><Value>atlas</Value></Tag><Tag><Key>cost-center</Key><Value>1234</Value></Tag></TagSet></Tagging>";
+        assert_eq!(to_string(&tagging).unwrap(), exp);
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Tagging xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><TagSet><Tag><Key>project</Key><Value>atlas</Value></Tag><Tag><Key>cost-center</Key><Value>1234</Value></Tag></TagSet></Tagging>"#;
+        let parsed: Tagging = from_str(input).unwrap();
+        assert_eq!(parsed, tagging);
+
+        let input = r#"<Tagging><TagSet></TagSet></Tagging>"#;
+        let parsed: Tagging = from_str(input).unwrap();
+        assert!(parsed.tag_set.tags.is_empty());
+    }
+
+    #[test]
+    fn test_validate_tags() {
+        let tag = |k: &str, v: &str| (k.to_string(), v.to_string());
+
+        assert!(validate_tags(&[tag("a", "b")], MAX_OBJECT_TAGS).is_ok());
+        assert!(validate_tags(&[tag("", "b")], MAX_OBJECT_TAGS).is_err());
+        assert!(validate_tags(&[tag(&"k".repeat(129), "b")], MAX_OBJECT_TAGS).is_err());
+        assert!(validate_tags(&[tag("a", &"v".repeat(257))], MAX_OBJECT_TAGS).is_err());
+        assert!(validate_tags(&[tag("a", "b"), tag("a", "c")], MAX_OBJECT_TAGS).is_err());
+
+        assert!(validate_tags(&[tag("team name", "a+b=c/d@e:f_g.h-i")], MAX_OBJECT_TAGS).is_ok());
+        assert!(validate_tags(&[tag("a*", "b")], MAX_OBJECT_TAGS).is_err());
+        assert!(validate_tags(&[tag("a", "<b>")], MAX_OBJECT_TAGS).is_err());
+
+        let many: Vec<_> = (0..11).map(|i| tag(&i.to_string(), "v")).collect();
+        assert!(validate_tags(&many, MAX_OBJECT_TAGS).is_err());
+        assert!(validate_tags(&many, MAX_BUCKET_TAGS).is_ok());
+
+        let many: Vec<_> = (0..1001).map(|i| tag(&i.to_string(), "v")).collect();
+        assert!(validate_tags(&many, MAX_BUCKET_TAGS).is_err());
+    }
+}
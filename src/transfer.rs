@@ -0,0 +1,688 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use md5::{Digest, Md5};
+use reqwest::blocking::Body;
+use tracing::instrument;
+
+use crate::cos::{Client, Contents, Error};
+use crate::error::{into_send_error, CosError, SendError};
+use crate::multipartupload::{MAX_PARTS, MIN_PART_SIZE};
+use crate::object::{content_type_from_extension, is_md5_etag, PutObjectOptions};
+use crate::progress::{Progress, ProgressReader};
+
+/// Files at least this large are uploaded with a multipart upload
+pub const MULTIPART_THRESHOLD: u64 = 100 * 1024 * 1024;
+/// Part size used by `upload_file`, raised for files too large to fit in `MAX_PARTS`
+pub const DEFAULT_PART_SIZE: usize = 16 * 1024 * 1024;
+
+/// The outcome of downloading one key with `Client::download_objects`.
+#[derive(Debug)]
+pub struct DownloadResult {
+    pub key: String,
+    pub path: PathBuf,
+    /// Bytes written
+    pub result: Result<u64, SendError>,
+}
+
+/// What `Client::sync_prefix` changed in the local directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SyncSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub skipped: usize,
+}
+
+/// Whether the local file already matches a listed object: same size and,
+/// when the ETag is a plain MD5, the same content hash.
+fn is_unchanged(path: &Path, obj: &Contents) -> Result<bool, Error> {
+    let meta = match fs::metadata(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    if meta.len() != obj.size {
+        return Ok(false);
+    }
+
+    if !is_md5_etag(&obj.etag) {
+        // multipart ETags aren't the MD5 of the content; size is all we can compare
+        return Ok(true);
+    }
+
+    Ok(file_md5(path)? == obj.etag.to_ascii_lowercase())
+}
+
+/// Hex MD5 of the contents of `path`.
+fn file_md5(path: &Path) -> io::Result<String> {
+    let mut f = File::open(path)?;
+    let mut hasher = Md5::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn local_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            local_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The local path for `key` under `dest_dir`, treating `/` in keys as directories.
+///
+/// Keys that would escape `dest_dir` (absolute, or containing `..`) are rejected.
+fn key_path(dest_dir: &Path, key: &str) -> Result<PathBuf, Error> {
+    let mut path = dest_dir.to_path_buf();
+
+    for part in key.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => {
+                return Err(CosError::Validation(format!(
+                    "key '{}' would be written outside the destination",
+                    key
+                ))
+                .into())
+            }
+            p => path.push(p),
+        }
+    }
+
+    if path == dest_dir {
+        return Err(CosError::Validation(format!("key '{}' has no file name", key)).into());
+    }
+
+    Ok(path)
+}
+
+/// A path next to `path` to download into before renaming into place.
+///
+/// The name is unique per call, so concurrent downloads of the same path,
+/// from this or another process, don't write into each other's file.
+fn temp_path(path: &Path) -> Result<PathBuf, Error> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("invalid download path '{}'", path.display()))?;
+
+    let tmp_name = format!(
+        ".{}.{}.{}.part",
+        name.to_string_lossy(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// A part size that keeps an upload of `size` bytes within `MAX_PARTS` parts.
+fn part_size_for(size: u64) -> usize {
+    let min = (size as usize).div_ceil(MAX_PARTS);
+    min.max(DEFAULT_PART_SIZE).max(MIN_PART_SIZE)
+}
+
+fn write_file<R: Read>(mut r: R, path: &Path) -> Result<u64, Error> {
+    let mut f = File::create(path)?;
+    let n = io::copy(&mut r, &mut f)?;
+    f.flush()?;
+    f.sync_all()?;
+    Ok(n)
+}
+
+impl Client {
+    /// Download an object into a file, returning the number of bytes written.
+    ///
+    /// The body is written to a temporary file in the same directory and renamed
+    /// over `path` once complete, so an interrupted download never leaves a
+    /// partial file at `path`.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn download_to(&self, bucket: &str, key: &str, path: &Path) -> Result<u64, Error> {
+        self._download_to(bucket, key, path, None)
+    }
+
+    /// Like `download_to`, reporting progress as the body is written.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn download_to_with_progress(
+        &self,
+        bucket: &str,
+        key: &str,
+        path: &Path,
+        progress: Arc<dyn Progress>,
+    ) -> Result<u64, Error> {
+        self._download_to(bucket, key, path, Some(progress))
+    }
+
+    fn _download_to(
+        &self,
+        bucket: &str,
+        key: &str,
+        path: &Path,
+        progress: Option<Arc<dyn Progress>>,
+    ) -> Result<u64, Error> {
+        let tmp = temp_path(path)?;
+
+        let res = self
+            .get_object_with_metadata(bucket, key)
+            .and_then(|obj| match progress {
+                Some(p) => {
+                    let total = Some(obj.metadata.content_length);
+                    write_file(ProgressReader::new(obj, total, p), &tmp)
+                }
+                None => write_file(obj, &tmp),
+            })
+            .and_then(|n| {
+                fs::rename(&tmp, path)?;
+                Ok(n)
+            });
+
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+
+        res
+    }
+
+    /// Download one object into `dest` in ranges of `chunk_size` bytes, with up
+    /// to `concurrency` ranges downloading at once, returning the object's size.
+    ///
+    /// Like `download_to` the object is written to a temporary file first. The
+    /// result is checked against the object's size, and against its MD5 when the
+    /// ETag is a plain MD5.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn download_ranged(
+        &self,
+        bucket: &str,
+        key: &str,
+        dest: &Path,
+        concurrency: usize,
+        chunk_size: u64,
+    ) -> Result<u64, Error> {
+        if chunk_size == 0 {
+            return Err(CosError::Validation("chunk size must not be 0".to_string()).into());
+        }
+
+        let metadata = self.head_object(bucket, key)?;
+        let size = metadata.content_length;
+        let tmp = temp_path(dest)?;
+
+        let res = self
+            .download_ranges(bucket, key, &tmp, size, concurrency, chunk_size)
+            .and_then(|()| {
+                let written = fs::metadata(&tmp)?.len();
+                if written != size {
+                    return Err(format!("downloaded {} bytes, expected {}", written, size).into());
+                }
+
+                if let Some(etag) = metadata.etag.filter(|e| is_md5_etag(e)) {
+                    if file_md5(&tmp)? != etag.to_ascii_lowercase() {
+                        return Err(format!("MD5 of '{}' does not match its ETag", key).into());
+                    }
+                }
+
+                fs::rename(&tmp, dest)?;
+                Ok(size)
+            });
+
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+
+        res
+    }
+
+    fn download_ranges(
+        &self,
+        bucket: &str,
+        key: &str,
+        path: &Path,
+        size: u64,
+        concurrency: usize,
+        chunk_size: u64,
+    ) -> Result<(), Error> {
+        File::create(path)?.set_len(size)?;
+
+        // u64 throughout, as a usize step would truncate on 32-bit targets
+        let next_start = Mutex::new(0u64);
+        // set by the first failing worker so the others stop taking ranges
+        let failed = AtomicBool::new(false);
+
+        let download = |start: u64| -> Result<(), Error> {
+            let end = (start + chunk_size).min(size) - 1;
+            let mut body = self.get_object_at_range(bucket, key, start, Some(end))?;
+
+            if body.range.map(|r| (r.start, r.end)) != Some((start, end)) {
+                return Err(format!("bytes {}-{} of '{}' were not served", start, end, key).into());
+            }
+
+            let mut f = OpenOptions::new().write(true).open(path)?;
+            f.seek(SeekFrom::Start(start))?;
+            let n = io::copy(&mut body, &mut f)?;
+            if n != end - start + 1 {
+                return Err(format!("short read for bytes {}-{} of '{}'", start, end, key).into());
+            }
+
+            Ok(())
+        };
+
+        let results: Vec<Result<(), SendError>> = thread::scope(|s| {
+            let workers: Vec<_> = (0..concurrency.max(1))
+                .map(|_| {
+                    s.spawn(|| loop {
+                        if failed.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+
+                        let start = {
+                            let mut next = next_start.lock().unwrap();
+                            let start = *next;
+                            *next = start.saturating_add(chunk_size);
+                            start
+                        };
+                        if start >= size {
+                            return Ok(());
+                        }
+
+                        if let Err(e) = download(start) {
+                            failed.store(true, Ordering::Relaxed);
+                            return Err(into_send_error(e));
+                        }
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|w| w.join().expect("download worker panicked"))
+                .collect()
+        });
+
+        results
+            .into_iter()
+            .try_for_each(|res| res.map_err(|e| e as Error))
+    }
+
+    /// Download many keys into `dest_dir`, with up to `concurrency` downloads
+    /// running at once.
+    ///
+    /// Keys map to paths below `dest_dir`, with `/` separated parts becoming
+    /// subdirectories. Results are returned in the order of `keys`.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn download_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+        dest_dir: &Path,
+        concurrency: usize,
+    ) -> Vec<DownloadResult> {
+        let queue = Mutex::new(keys.iter().enumerate());
+
+        let download = |key: &str, path: &Path| -> Result<u64, Error> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.download_to(bucket, key, path)
+        };
+
+        let mut results: Vec<(usize, DownloadResult)> = thread::scope(|s| {
+            let workers: Vec<_> = (0..concurrency.max(1))
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut done = Vec::new();
+
+                        loop {
+                            let next = queue.lock().unwrap().next();
+                            let Some((i, key)) = next else {
+                                break;
+                            };
+
+                            let (path, result) = match key_path(dest_dir, key) {
+                                Ok(path) => {
+                                    let res = download(key, &path);
+                                    (path, res)
+                                }
+                                Err(e) => (PathBuf::new(), Err(e)),
+                            };
+
+                            done.push((
+                                i,
+                                DownloadResult {
+                                    key: key.clone(),
+                                    path,
+                                    result: result.map_err(into_send_error),
+                                },
+                            ));
+                        }
+
+                        done
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("download worker panicked"))
+                .collect()
+        });
+
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+
+    /// Make `dest_dir` mirror the objects under `prefix`, like `aws s3 sync`.
+    ///
+    /// Keys are written relative to `prefix`. Objects whose local copy differs
+    /// in size or content are downloaded, unchanged ones are skipped, and with
+    /// `delete_extra` local files that no longer exist under the prefix are removed.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn sync_prefix(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        dest_dir: &Path,
+        delete_extra: bool,
+    ) -> Result<SyncSummary, Error> {
+        let mut summary = SyncSummary::default();
+        let mut expected = HashSet::new();
+
+        // list everything up front, so a failed listing can't cause local deletes
+        let prefix_opt = Some(prefix.to_string()).filter(|p| !p.is_empty());
+        let mut objects = Vec::new();
+        let mut token = None;
+        loop {
+            let page = self.list_objects_page(bucket, prefix_opt.clone(), token, None)?;
+            objects.extend(page.contents);
+
+            match page.next_token {
+                Some(t) => token = Some(t),
+                None => break,
+            }
+        }
+
+        for obj in objects {
+            let rel = obj.key.strip_prefix(prefix).unwrap_or(&obj.key);
+            if rel.is_empty() || rel.ends_with('/') {
+                // folder placeholder objects
+                continue;
+            }
+
+            let path = key_path(dest_dir, rel)?;
+            expected.insert(path.clone());
+
+            if is_unchanged(&path, &obj)? {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let existed = path.exists();
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.download_to(bucket, &obj.key, &path)?;
+
+            if existed {
+                summary.updated += 1;
+            } else {
+                summary.added += 1;
+            }
+        }
+
+        if delete_extra && dest_dir.is_dir() {
+            let mut files = Vec::new();
+            local_files(dest_dir, &mut files)?;
+
+            for f in files {
+                if !expected.contains(&f) {
+                    fs::remove_file(&f)?;
+                    summary.deleted += 1;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Upload a file, using a single PUT for small files and a multipart upload
+    /// for files of at least [`MULTIPART_THRESHOLD`] bytes.
+    ///
+    /// The Content-Type is set from the file extension when it is recognized.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn upload_file(&self, bucket: &str, key: &str, path: &Path) -> Result<(), Error> {
+        self._upload_file(bucket, key, path, None)
+    }
+
+    /// Like `upload_file`, reporting progress as the file is read.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn upload_file_with_progress(
+        &self,
+        bucket: &str,
+        key: &str,
+        path: &Path,
+        progress: Arc<dyn Progress>,
+    ) -> Result<(), Error> {
+        self._upload_file(bucket, key, path, Some(progress))
+    }
+
+    fn _upload_file(
+        &self,
+        bucket: &str,
+        key: &str,
+        path: &Path,
+        progress: Option<Arc<dyn Progress>>,
+    ) -> Result<(), Error> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        let options = PutObjectOptions {
+            content_type: content_type_from_extension(&path.to_string_lossy()).map(String::from),
+            ..Default::default()
+        };
+
+        let part_size = part_size_for(size);
+
+        match progress {
+            Some(p) => {
+                let r = ProgressReader::new(file, Some(size), p);
+
+                if size < MULTIPART_THRESHOLD {
+                    let body = Body::sized(r, size);
+                    self.put_object_with_options(bucket, key, body, &options)?;
+                    Ok(())
+                } else {
+                    self.upload_multipart(bucket, key, r, part_size, &options)
+                }
+            }
+            None => {
+                if size < MULTIPART_THRESHOLD {
+                    self.put_object_with_options(bucket, key, file, &options)?;
+                    Ok(())
+                } else {
+                    self.upload_multipart(bucket, key, file, part_size, &options)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_path_in_same_dir() {
+        let tmp = temp_path(Path::new("/data/out/file.bin")).unwrap();
+        assert_eq!(tmp.parent(), Some(Path::new("/data/out")));
+        assert_ne!(tmp, Path::new("/data/out/file.bin"));
+        assert_ne!(tmp, temp_path(Path::new("/data/out/file.bin")).unwrap());
+
+        assert!(temp_path(Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn test_key_path() {
+        let dest = Path::new("/data");
+        assert_eq!(
+            key_path(dest, "logs/2023/a.txt").unwrap(),
+            Path::new("/data/logs/2023/a.txt")
+        );
+        assert_eq!(key_path(dest, "/a//b").unwrap(), Path::new("/data/a/b"));
+        assert!(key_path(dest, "../etc/passwd").is_err());
+        assert!(key_path(dest, "a/../../b").is_err());
+        assert!(key_path(dest, "/").is_err());
+    }
+
+    #[test]
+    fn test_download_objects() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(200, "data").respond(200, "data");
+
+        let dest = std::env::temp_dir().join(format!("cos-download-{}", std::process::id()));
+        let keys = vec![
+            "a/b.txt".to_string(),
+            "../escape".to_string(),
+            "c.txt".to_string(),
+        ];
+
+        let results = client.download_objects("bucket", &keys, &dest, 2);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].key, "a/b.txt");
+        assert_eq!(*results[0].result.as_ref().unwrap(), 4);
+        assert!(results[1].result.is_err());
+        assert_eq!(fs::read_to_string(dest.join("a/b.txt")).unwrap(), "data");
+        assert_eq!(fs::read_to_string(dest.join("c.txt")).unwrap(), "data");
+
+        fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_sync_prefix() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let entry = crate::transport::mock::contents;
+        let listing = format!(
+            "<ListBucketResult><KeyCount>4</KeyCount><MaxKeys>1000</MaxKeys>{}{}{}{}</ListBucketResult>",
+            entry("p/", "d41d8cd98f00b204e9800998ecf8427e", 0),
+            entry("p/new.txt", "22af645d1859cb5ca6da0c484f1f37ea", 3),
+            entry("p/same.txt", &hex::encode(Md5::digest(b"same")), 4),
+            entry("p/sub/changed.txt", "0c4ec2bb4cba1e2e0a9ccd3ac1cb6c0d", 7),
+        );
+        transport
+            .respond(200, &listing)
+            .respond(200, "new")
+            .respond(200, "changed");
+
+        let dest = std::env::temp_dir().join(format!("cos-sync-{}", std::process::id()));
+        fs::create_dir_all(dest.join("sub")).unwrap();
+        fs::write(dest.join("same.txt"), "same").unwrap();
+        fs::write(dest.join("sub/changed.txt"), "old").unwrap();
+        fs::write(dest.join("extra.txt"), "extra").unwrap();
+
+        let summary = client.sync_prefix("bucket", "p/", &dest, true).unwrap();
+
+        assert_eq!(
+            summary,
+            SyncSummary {
+                added: 1,
+                updated: 1,
+                deleted: 1,
+                skipped: 1
+            }
+        );
+        assert_eq!(fs::read_to_string(dest.join("new.txt")).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(dest.join("sub/changed.txt")).unwrap(),
+            "changed"
+        );
+        assert!(!dest.join("extra.txt").exists());
+        assert_eq!(transport.requests().len(), 3);
+
+        fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_download_ranged() {
+        let (client, transport) = crate::transport::mock::client();
+
+        let mut hasher = Md5::new();
+        hasher.update(b"0123456789");
+        let etag = format!("\"{}\"", hex::encode(hasher.finalize()));
+
+        transport
+            .respond_with_headers(
+                200,
+                vec![("content-length", "10".to_string()), ("etag", etag)],
+                "",
+            )
+            .respond_with_headers(
+                206,
+                vec![("content-range", "bytes 0-3/10".to_string())],
+                "0123",
+            )
+            .respond_with_headers(
+                206,
+                vec![("content-range", "bytes 4-7/10".to_string())],
+                "4567",
+            )
+            .respond_with_headers(
+                206,
+                vec![("content-range", "bytes 8-9/10".to_string())],
+                "89",
+            );
+
+        let dest = std::env::temp_dir().join(format!("cos-ranged-{}", std::process::id()));
+        assert_eq!(client.download_ranged("b", "k", &dest, 1, 4).unwrap(), 10);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "0123456789");
+
+        let ranges: Vec<_> = transport.requests()[1..]
+            .iter()
+            .map(|r| r.headers["range"].to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(ranges, vec!["bytes=0-3", "bytes=4-7", "bytes=8-9"]);
+
+        fs::remove_file(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_part_size_for() {
+        assert_eq!(part_size_for(0), DEFAULT_PART_SIZE);
+        assert_eq!(part_size_for(MULTIPART_THRESHOLD), DEFAULT_PART_SIZE);
+
+        let huge = 1024 * 1024 * 1024 * 1024;
+        assert!(part_size_for(huge) as u64 * MAX_PARTS as u64 >= huge);
+    }
+}
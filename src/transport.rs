@@ -0,0 +1,323 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::{Request, RequestBuilder, Response};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+use tracing::debug;
+
+use crate::cos::{Client, Error, RequestContext};
+use crate::credentials::{fetch_token, CredentialProvider};
+use crate::hmac::{sign_request, HmacCredentials};
+
+/// Executes the HTTP requests built by `cos::Client`.
+///
+/// `reqwest::blocking::Client` is the default implementation; others can
+/// record requests or serve canned responses in tests.
+pub trait Transport: Send + Sync {
+    fn execute(&self, request: Request) -> Result<Response, Error>;
+}
+
+impl Transport for reqwest::blocking::Client {
+    fn execute(&self, request: Request) -> Result<Response, Error> {
+        Ok(reqwest::blocking::Client::execute(self, request)?)
+    }
+}
+
+/// How a client authenticates its requests.
+#[derive(Clone)]
+pub(crate) enum Auth {
+    /// An IAM bearer token in the `Authorization` header
+    Bearer(Arc<dyn CredentialProvider>),
+    /// An AWS Signature Version 4 made with HMAC keys
+    Hmac(Arc<HmacCredentials>),
+    /// No credentials, for reading public buckets
+    Anonymous,
+}
+
+impl Auth {
+    /// Add the authentication headers to `request`; `refresh` fetches a new
+    /// token rather than a cached one.
+    fn apply(&self, request: &mut Request, refresh: bool) -> Result<(), Error> {
+        match self {
+            Auth::Bearer(tm) => {
+                let token = fetch_token(tm.as_ref(), refresh)?;
+                request.headers_mut().insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {}", token.access_token))?,
+                );
+                Ok(())
+            }
+            Auth::Hmac(creds) => sign_request(creds, request),
+            Auth::Anonymous => Ok(()),
+        }
+    }
+}
+
+/// Longest delay between two retries, however many came before
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(20);
+
+pub(crate) trait SendVia {
+    /// Build the request, authenticate it and send it through the client's transport.
+    ///
+    /// A request rejected with 401 is sent once more with a refreshed token,
+    /// and one that fails with a server error or can't connect is retried up
+    /// to `Client::max_retries` times, unless its body is a stream that can't
+    /// be replayed.
+    fn send_via(self, client: &Client) -> Result<Response, Error>;
+}
+
+impl SendVia for RequestBuilder {
+    fn send_via(self, client: &Client) -> Result<Response, Error> {
+        let mut request = self.build()?;
+        let mut attempt = 0;
+
+        loop {
+            let replay = if attempt < client.max_retries {
+                request.try_clone()
+            } else {
+                None
+            };
+
+            let result = send_once(request, client);
+
+            let retryable = match &result {
+                Ok(response) => is_retryable(response.status()),
+                Err(e) => e
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(|e| e.is_connect()),
+            };
+
+            match replay {
+                Some(next) if retryable => {
+                    let delay = client
+                        .retry_backoff
+                        .saturating_mul(1 << attempt.min(16))
+                        .min(MAX_RETRY_BACKOFF);
+                    debug!(
+                        "{} {} failed, retrying in {:?}",
+                        next.method(),
+                        next.url(),
+                        delay
+                    );
+
+                    thread::sleep(delay);
+                    attempt += 1;
+                    request = next;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+/// Responses worth sending the request again for: throttling, including COS's
+/// 503 `SlowDown`, and server errors other than 501.
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED)
+}
+
+/// Authenticate and send `request` once, or twice if the token was rejected.
+fn send_once(mut request: Request, client: &Client) -> Result<Response, Error> {
+    let retry = match client.auth {
+        Auth::Bearer(_) => request.try_clone(),
+        Auth::Hmac(_) | Auth::Anonymous => None,
+    };
+
+    client.auth.apply(&mut request, false)?;
+    let response = client.transport.execute(request)?;
+
+    let response = match retry {
+        Some(mut retry) if response.status() == StatusCode::UNAUTHORIZED => {
+            debug!(
+                "{} {} was unauthorized, refreshing token",
+                retry.method(),
+                retry.url()
+            );
+
+            client.auth.apply(&mut retry, true)?;
+            client.transport.execute(retry)?
+        }
+        _ => response,
+    };
+
+    // logged within the operation's span, to correlate it with COS's logs
+    let context = RequestContext::from_headers(response.headers());
+    debug!(
+        status = response.status().as_u16(),
+        request_id = context.request_id.as_deref().unwrap_or(""),
+        "response received"
+    );
+
+    Ok(response)
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use ibmcloud_iam::token::Token;
+    use reqwest::blocking::{Request, Response};
+    use reqwest::Method;
+
+    use super::Transport;
+    use crate::cos::{Client, Error};
+
+    /// A request as seen by `MockTransport`.
+    #[derive(Debug, Clone)]
+    pub(crate) struct Recorded {
+        pub method: Method,
+        pub url: String,
+        pub headers: reqwest::header::HeaderMap,
+        pub body: Option<Vec<u8>>,
+    }
+
+    struct Canned {
+        status: u16,
+        headers: Vec<(&'static str, String)>,
+        body: String,
+    }
+
+    /// Records requests and answers them with queued responses, in order.
+    #[derive(Default)]
+    pub(crate) struct MockTransport {
+        responses: Mutex<VecDeque<Canned>>,
+        pub requests: Mutex<Vec<Recorded>>,
+    }
+
+    impl MockTransport {
+        pub fn respond(&self, status: u16, body: &str) -> &Self {
+            self.respond_with_headers(status, vec![], body)
+        }
+
+        pub fn respond_with_headers(
+            &self,
+            status: u16,
+            headers: Vec<(&'static str, String)>,
+            body: &str,
+        ) -> &Self {
+            self.responses.lock().unwrap().push_back(Canned {
+                status,
+                headers,
+                body: body.to_string(),
+            });
+            self
+        }
+
+        pub fn requests(&self) -> Vec<Recorded> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn execute(&self, request: Request) -> Result<Response, Error> {
+            self.requests.lock().unwrap().push(Recorded {
+                method: request.method().clone(),
+                url: request.url().to_string(),
+                headers: request.headers().clone(),
+                body: request
+                    .body()
+                    .and_then(|b| b.as_bytes())
+                    .map(|b| b.to_vec()),
+            });
+
+            let canned = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or("no response queued")?;
+
+            let mut builder = http::Response::builder().status(canned.status);
+            for (name, value) in canned.headers {
+                builder = builder.header(name, value);
+            }
+
+            Ok(builder.body(canned.body)?.into())
+        }
+    }
+
+    /// A client for `s3.example.com` whose requests go to a fresh `MockTransport`.
+    pub(crate) fn client() -> (Client, Arc<MockTransport>) {
+        let token = Token {
+            access_token: "token".to_string(),
+            token_type: "Bearer".to_string(),
+            refresh_token: String::new(),
+            expiry: Instant::now() + Duration::from_secs(3600),
+        };
+
+        let transport = Arc::new(MockTransport::default());
+        let client = Client::with_transport(Arc::new(token), "s3.example.com", transport.clone());
+
+        (client, transport)
+    }
+
+    /// Like `client`, for an HMAC client with access key `id` and secret `secret`.
+    pub(crate) fn hmac_client() -> (crate::hmac::Client, Arc<MockTransport>) {
+        let transport = Arc::new(MockTransport::default());
+        let mut client = crate::hmac::Client::new("https://s3.example.com", "id", "secret");
+        client.inner.transport = transport.clone();
+
+        (client, transport)
+    }
+
+    /// A `<Contents>` entry of an object listing.
+    pub(crate) fn contents(key: &str, etag: &str, size: u64) -> String {
+        format!("<Contents><Key>{}</Key><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>\"{}\"</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>", key, etag, size)
+    }
+
+    /// A ListObjectsV2 page of `keys`, truncated when `token` is set.
+    pub(crate) fn listing(keys: &[&str], token: Option<&str>) -> String {
+        let contents: String = keys.iter().map(|k| contents(k, "abc", 1)).collect();
+        let token = token
+            .map(|t| format!("<NextContinuationToken>{}</NextContinuationToken>", t))
+            .unwrap_or_default();
+        format!("<ListBucketResult><KeyCount>{}</KeyCount><MaxKeys>2</MaxKeys><IsTruncated>{}</IsTruncated>{}{}</ListBucketResult>", keys.len(), !token.is_empty(), token, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries_server_errors() {
+        let (mut client, transport) = mock::client();
+        client.max_retries = 2;
+        client.retry_backoff = Duration::ZERO;
+
+        transport
+            .respond(503, "<Error><Code>SlowDown</Code></Error>")
+            .respond(500, "")
+            .respond(204, "");
+        client.delete_object("b", "k").unwrap();
+        assert_eq!(transport.requests().len(), 3);
+
+        transport.respond(500, "").respond(500, "").respond(500, "");
+        assert!(client.delete_object("b", "k").is_err());
+        assert_eq!(transport.requests().len(), 6);
+
+        // nor is an operation COS doesn't implement
+        transport.respond(501, "");
+        assert!(client.delete_object("b", "k").is_err());
+        assert_eq!(transport.requests().len(), 7);
+    }
+}
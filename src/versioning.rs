@@ -0,0 +1,532 @@
+// Copyright 2023 Mathew Odden <mathewrodden@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+
+use chrono::Utc;
+use quick_xml::{de::from_str, se::to_string};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{error, instrument};
+use urlencoding::encode;
+
+use crate::cos::{check_response, read_checked, Client, Error, RequestContext};
+use crate::error::CosError;
+use crate::object::{deserialize_etag, ObjectMetadata};
+use crate::transport::SendVia;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct VersioningConfiguration {
+    #[serde(
+        rename = "$unflatten=Status",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersioningStatus {
+    /// Versioning has never been enabled on the bucket
+    Unversioned,
+    Enabled,
+    Suspended,
+}
+
+/// The result of deleting an object (or one version of it).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeleteObjectOutput {
+    /// Whether the delete created, or removed, a delete marker
+    pub delete_marker: bool,
+    /// The version id of the delete marker or deleted version, in versioned buckets
+    pub version_id: Option<String>,
+    pub context: RequestContext,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ObjectVersion {
+    #[serde(rename = "$unflatten=Key")]
+    pub key: String,
+    #[serde(rename = "$unflatten=VersionId")]
+    pub version_id: String,
+    #[serde(rename = "$unflatten=IsLatest")]
+    pub is_latest: bool,
+    #[serde(rename = "$unflatten=LastModified")]
+    pub last_modified: String,
+    #[serde(
+        rename = "$unflatten=ETag",
+        default,
+        deserialize_with = "deserialize_etag"
+    )]
+    pub etag: String,
+    #[serde(rename = "$unflatten=Size", default)]
+    pub size: u64,
+    #[serde(rename = "$unflatten=StorageClass", default)]
+    pub storage_class: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct DeleteMarker {
+    #[serde(rename = "$unflatten=Key")]
+    pub key: String,
+    #[serde(rename = "$unflatten=VersionId")]
+    pub version_id: String,
+    #[serde(rename = "$unflatten=IsLatest")]
+    pub is_latest: bool,
+    #[serde(rename = "$unflatten=LastModified")]
+    pub last_modified: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum VersionEntry {
+    Version(ObjectVersion),
+    DeleteMarker(DeleteMarker),
+}
+
+impl VersionEntry {
+    pub fn key(&self) -> &str {
+        match self {
+            VersionEntry::Version(v) => &v.key,
+            VersionEntry::DeleteMarker(d) => &d.key,
+        }
+    }
+
+    pub fn version_id(&self) -> &str {
+        match self {
+            VersionEntry::Version(v) => &v.version_id,
+            VersionEntry::DeleteMarker(d) => &d.version_id,
+        }
+    }
+
+    pub fn is_latest(&self) -> bool {
+        match self {
+            VersionEntry::Version(v) => v.is_latest,
+            VersionEntry::DeleteMarker(d) => d.is_latest,
+        }
+    }
+}
+
+// <Version> and <DeleteMarker> elements are interleaved in key order, so the
+// result is read as a flat sequence of elements
+#[derive(Deserialize, Debug)]
+enum ListVersionsElement {
+    Version(ObjectVersion),
+    DeleteMarker(DeleteMarker),
+    IsTruncated(bool),
+    NextKeyMarker(String),
+    NextVersionIdMarker(String),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListVersionsXml {
+    #[serde(rename = "$value", default)]
+    elements: Vec<ListVersionsElement>,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct ListVersionsResult {
+    pub entries: Vec<VersionEntry>,
+    pub is_truncated: bool,
+    pub next_key_marker: Option<String>,
+    pub next_version_id_marker: Option<String>,
+}
+
+fn parse_list_versions(text: &str) -> Result<ListVersionsResult, Error> {
+    let xml: ListVersionsXml = from_str(text)?;
+    let mut res = ListVersionsResult::default();
+
+    for el in xml.elements {
+        match el {
+            ListVersionsElement::Version(v) => res.entries.push(VersionEntry::Version(v)),
+            ListVersionsElement::DeleteMarker(d) => res.entries.push(VersionEntry::DeleteMarker(d)),
+            ListVersionsElement::IsTruncated(t) => res.is_truncated = t,
+            ListVersionsElement::NextKeyMarker(m) if !m.is_empty() => res.next_key_marker = Some(m),
+            ListVersionsElement::NextVersionIdMarker(m) if !m.is_empty() => {
+                res.next_version_id_marker = Some(m)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(res)
+}
+
+fn build_list_versions_url(
+    bucket_url: &str,
+    prefix: &Option<String>,
+    key_marker: &Option<String>,
+    version_id_marker: &Option<String>,
+) -> String {
+    let mut url = format!("{}?versions", bucket_url);
+
+    if let Some(pre) = prefix {
+        url.push_str(&format!("&prefix={}", encode(pre)));
+    }
+
+    if let Some(m) = key_marker {
+        url.push_str(&format!("&key-marker={}", encode(m)));
+    }
+
+    if let Some(m) = version_id_marker {
+        url.push_str(&format!("&version-id-marker={}", encode(m)));
+    }
+
+    url
+}
+
+pub(crate) fn object_url(bucket_url: &str, key: &str, version_id: Option<&str>) -> String {
+    match version_id {
+        Some(v) => format!("{}{}?versionId={}", bucket_url, key, encode(v)),
+        None => format!("{}{}", bucket_url, key),
+    }
+}
+
+impl Client {
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn put_bucket_versioning(&self, bucket: &str, enabled: bool) -> Result<(), Error> {
+        let c = &self.client;
+        let url = format!("{}?versioning", self.bucket_url(bucket));
+
+        let status = if enabled { "Enabled" } else { "Suspended" };
+        let payload = to_string(&VersioningConfiguration {
+            status: Some(status.to_string()),
+        })?;
+
+        let response = c.put(url).body(payload).send_via(self)?;
+
+        check_response(response)?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn get_bucket_versioning(&self, bucket: &str) -> Result<VersioningStatus, Error> {
+        let c = &self.client;
+        let url = format!("{}?versioning", self.bucket_url(bucket));
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        let config: VersioningConfiguration = from_str(&text)?;
+
+        match config.status.as_deref() {
+            Some("Enabled") => Ok(VersioningStatus::Enabled),
+            Some("Suspended") => Ok(VersioningStatus::Suspended),
+            _ => Ok(VersioningStatus::Unversioned),
+        }
+    }
+
+    /// Iterate over every version and delete marker of the objects under `prefix`.
+    pub fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+    ) -> VersionIterator<'_> {
+        VersionIterator::new(self, bucket, prefix)
+    }
+
+    /// Fetch a single page of a version listing, for callers doing their own
+    /// pagination or that need to see listing errors.
+    ///
+    /// Pass the previous page's `next_key_marker` and `next_version_id_marker`
+    /// to get the following page.
+    #[instrument(skip_all, fields(bucket = bucket))]
+    pub fn list_object_versions_page(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+        key_marker: Option<String>,
+        version_id_marker: Option<String>,
+    ) -> Result<ListVersionsResult, Error> {
+        self._list_object_versions(bucket, &prefix, &key_marker, &version_id_marker)
+    }
+
+    fn _list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &Option<String>,
+        key_marker: &Option<String>,
+        version_id_marker: &Option<String>,
+    ) -> Result<ListVersionsResult, Error> {
+        let c = &self.client;
+        let url = build_list_versions_url(
+            &self.bucket_url(bucket),
+            prefix,
+            key_marker,
+            version_id_marker,
+        );
+
+        let response = c.get(url).send_via(self)?;
+
+        let text = read_checked(response)?;
+        parse_list_versions(&text)
+    }
+
+    /// Like `head_object`, for a specific version of the object when `version_id` is given.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn head_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectMetadata, Error> {
+        let c = &self.client;
+        let url = object_url(&self.bucket_url(bucket), key, version_id);
+
+        let response = c.head(url).send_via(self)?;
+
+        let r = check_response(response)?;
+        Ok(ObjectMetadata::from_headers(r.headers()))
+    }
+
+    /// Delete an object, or permanently delete one version of it when `version_id` is given.
+    ///
+    /// In a versioned bucket deleting without a version id only adds a delete marker,
+    /// whose version id is returned.
+    #[instrument(skip_all, fields(bucket = bucket, key = key))]
+    pub fn delete_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<DeleteObjectOutput, Error> {
+        let c = &self.client;
+        let url = object_url(&self.bucket_url(bucket), key, version_id);
+
+        let response = c.delete(url).send_via(self)?;
+
+        let r = match check_response(response) {
+            Ok(r) => r,
+            Err(e) => return Err(self.locked_error(bucket, key, version_id, e)),
+        };
+        let headers = r.headers();
+
+        Ok(DeleteObjectOutput {
+            delete_marker: headers
+                .get("x-amz-delete-marker")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            version_id: headers
+                .get("x-amz-version-id")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string()),
+            context: RequestContext::from_headers(headers),
+        })
+    }
+
+    /// COS refuses to delete a locked object with a generic 403, so check the
+    /// object's lock state to tell it apart from missing permissions.
+    fn locked_error(&self, bucket: &str, key: &str, version_id: Option<&str>, err: Error) -> Error {
+        match err.downcast_ref::<CosError>() {
+            Some(CosError::Api(e)) if e.status == StatusCode::FORBIDDEN => {}
+            _ => return err,
+        }
+
+        match self.head_object_version(bucket, key, version_id) {
+            Ok(meta) if meta.legal_hold || meta.retain_until.is_some_and(|d| d > Utc::now()) => {
+                CosError::ObjectLocked {
+                    bucket: bucket.to_string(),
+                    key: key.to_string(),
+                    retain_until: meta.retain_until,
+                    legal_hold: meta.legal_hold,
+                }
+                .into()
+            }
+            _ => err,
+        }
+    }
+}
+
+pub struct VersionIterator<'a> {
+    client: &'a Client,
+    bucket: String,
+    prefix: Option<String>,
+    key_marker: Option<String>,
+    version_id_marker: Option<String>,
+    results: VecDeque<VersionEntry>,
+    complete: bool,
+}
+
+impl<'a> VersionIterator<'a> {
+    pub fn new(client: &'a Client, bucket: &str, prefix: Option<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            prefix,
+            key_marker: None,
+            version_id_marker: None,
+            results: VecDeque::new(),
+            complete: false,
+        }
+    }
+}
+
+impl Iterator for VersionIterator<'_> {
+    type Item = VersionEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.results.is_empty() {
+            if self.complete {
+                return None;
+            }
+
+            match self.client._list_object_versions(
+                &self.bucket,
+                &self.prefix,
+                &self.key_marker,
+                &self.version_id_marker,
+            ) {
+                Ok(page) => {
+                    self.results.extend(page.entries);
+
+                    if page.is_truncated && page.next_key_marker.is_some() {
+                        self.key_marker = page.next_key_marker;
+                        self.version_id_marker = page.next_version_id_marker;
+                    } else {
+                        self.complete = true;
+                    }
+                }
+                Err(e) => {
+                    error!(e);
+                    return None;
+                }
+            }
+        }
+
+        self.results.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioning_configuration_xml() {
+        let config = VersioningConfiguration {
+            status: Some("Enabled".to_string()),
+        };
+        let exp = "<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>";
+        assert_eq!(to_string(&config).unwrap(), exp);
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/"/>"#;
+        let config: VersioningConfiguration = from_str(input).unwrap();
+        assert_eq!(config.status, None);
+    }
+
+    #[test]
+    fn test_object_url_with_version() {
+        assert_eq!(
+            object_url("https://b.cos.example.com/", "k", Some("v1+/=")),
+            "https://b.cos.example.com/k?versionId=v1%2B%2F%3D"
+        );
+        assert_eq!(
+            object_url("https://b.cos.example.com/", "k", None),
+            "https://b.cos.example.com/k"
+        );
+    }
+
+    #[test]
+    fn test_parse_list_versions() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>bucket</Name><Prefix></Prefix><KeyMarker></KeyMarker><VersionIdMarker></VersionIdMarker><NextKeyMarker>b.txt</NextKeyMarker><NextVersionIdMarker>v3</NextVersionIdMarker><MaxKeys>3</MaxKeys><Delimiter></Delimiter><IsTruncated>true</IsTruncated><Version><Key>a.txt</Key><VersionId>v1</VersionId><IsLatest>true</IsLatest><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>"abc"</ETag><Size>12</Size><Owner><ID>o</ID><DisplayName>o</DisplayName></Owner><StorageClass>STANDARD</StorageClass></Version><DeleteMarker><Owner><ID>o</ID><DisplayName>o</DisplayName></Owner><Key>b.txt</Key><VersionId>v2</VersionId><IsLatest>true</IsLatest><LastModified>2023-01-02T00:00:00.000Z</LastModified></DeleteMarker><Version><Key>b.txt</Key><VersionId>v3</VersionId><IsLatest>false</IsLatest><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>"def"</ETag><Size>7</Size><Owner><ID>o</ID><DisplayName>o</DisplayName></Owner><StorageClass>STANDARD</StorageClass></Version></ListVersionsResult>"#;
+
+        let res = parse_list_versions(input).unwrap();
+
+        assert!(res.is_truncated);
+        assert_eq!(res.next_key_marker.as_deref(), Some("b.txt"));
+        assert_eq!(res.next_version_id_marker.as_deref(), Some("v3"));
+
+        let summary: Vec<_> = res
+            .entries
+            .iter()
+            .map(|e| (e.key(), e.version_id(), e.is_latest()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a.txt", "v1", true),
+                ("b.txt", "v2", true),
+                ("b.txt", "v3", false)
+            ]
+        );
+        assert!(matches!(res.entries[1], VersionEntry::DeleteMarker(_)));
+        assert!(matches!(&res.entries[2], VersionEntry::Version(v) if v.size == 7));
+    }
+
+    #[test]
+    fn test_build_list_versions_url() {
+        let url = build_list_versions_url(
+            "https://b.cos.example.com/",
+            &Some("logs/2023 Q1/".to_string()),
+            &Some("k".to_string()),
+            &Some("v+1".to_string()),
+        );
+        assert_eq!(
+            url,
+            "https://b.cos.example.com/?versions&prefix=logs%2F2023%20Q1%2F&key-marker=k&version-id-marker=v%2B1"
+        );
+    }
+
+    #[test]
+    fn test_delete_locked_object() {
+        let denied = "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>";
+
+        let (client, transport) = crate::transport::mock::client();
+        transport
+            .respond(403, denied)
+            .respond_with_headers(
+                200,
+                vec![(
+                    "x-amz-object-lock-retain-until-date",
+                    "2999-01-01T00:00:00Z".to_string(),
+                )],
+                "",
+            )
+            .respond(403, denied)
+            .respond(200, "");
+
+        let err = client.delete_object("b", "k").unwrap_err();
+        match err.downcast_ref::<CosError>() {
+            Some(CosError::ObjectLocked {
+                retain_until: Some(date),
+                legal_hold: false,
+                ..
+            }) => assert_eq!(date.to_rfc3339(), "2999-01-01T00:00:00+00:00"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert_eq!(transport.requests()[1].method, reqwest::Method::HEAD);
+
+        // an unlocked object keeps the original error
+        let err = client.delete_object("b", "k").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CosError>(),
+            Some(CosError::Api(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_object_versions_page_error() {
+        let (client, transport) = crate::transport::mock::client();
+        transport.respond(500, "<Error><Code>InternalError</Code></Error>");
+
+        assert!(client
+            .list_object_versions_page("b", Some("k".to_string()), None, None)
+            .is_err());
+        assert_eq!(
+            transport.requests()[0].url,
+            "https://b.s3.example.com/?versions&prefix=k"
+        );
+    }
+}